@@ -9,7 +9,7 @@ fn parse_f64(s: &str) -> f64 {
 }
 
 fn read_data(name: &str) -> Vec<(f64,f64)> {
-    let f = File::open(name).expect(&format!("cannot open {}",name));
+    let f = File::open(name).unwrap_or_else(|_| panic!("cannot open {}",name));
     let buff = io::BufReader::new(f);
     buff.lines()
         .map(|line| line.unwrap().split(',').take(2)
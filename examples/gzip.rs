@@ -0,0 +1,19 @@
+// writing compressed HTML by wrapping the output in a gzip encoder.
+// Run with `cargo run --example gzip --features flate2`
+extern crate flot;
+extern crate flate2;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::File;
+
+fn main() {
+    let page = flot::Page::new("Compressed");
+
+    let p = page.plot("Sine").size(500,300);
+    p.lines("sin",flot::mapv(flot::range(0.0,20.0,0.01),f64::sin));
+
+    let f = File::create("gzip.html.gz").expect("cannot create file");
+    let mut gz = GzEncoder::new(f,Compression::default());
+    page.render_to_writer(&mut gz).expect("i/o error");
+    gz.finish().expect("i/o error");
+}
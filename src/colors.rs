@@ -0,0 +1,53 @@
+//! Colour palettes and gradients for series colours.
+//!
+//! Colours are HTML hex strings like "#1f77b4", as accepted by
+//! `Series::color`.
+
+/// the ten colours used by D3's `category10` scheme
+pub const CATEGORY10: &[&str] = &[
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd",
+    "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf",
+];
+
+/// the Okabe-Ito palette, which stays distinguishable
+/// for the common kinds of colour blindness
+pub const COLORBLIND: &[&str] = &[
+    "#e69f00", "#56b4e9", "#009e73", "#f0e442",
+    "#0072b2", "#d55e00", "#cc79a7", "#000000",
+];
+
+/// muted colours that work well on a dark background
+pub const PASTEL: &[&str] = &[
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072",
+    "#80b1d3", "#fdb462", "#b3de69", "#fccde5",
+];
+
+// parse "#rgb" or "#rrggbb"
+fn parse_hex(color: &str) -> (u8,u8,u8) {
+    let hex = color.trim_start_matches('#');
+    let digit = |i: usize, len: usize| u8::from_str_radix(&hex[i..i+len],16).ok();
+    let rgb = match hex.len() {
+        3 => (digit(0,1),digit(1,1),digit(2,1)),
+        6 => (digit(0,2),digit(2,2),digit(4,2)),
+        _ => (None,None,None)
+    };
+    match rgb {
+        (Some(r),Some(g),Some(b)) if hex.len() == 3 => (r*17,g*17,b*17),
+        (Some(r),Some(g),Some(b)) => (r,g,b),
+        _ => panic!("{:?} is not a hex colour like #rrggbb",color)
+    }
+}
+
+/// `n` colours interpolated in RGB space from `start` to `end`,
+/// which are hex colours like "#00f" or "#0000ff".
+/// Useful for giving an ordered set of series related colours.
+/// Panics if either colour is not a hex colour, such as a name like "red".
+pub fn gradient(start: &str, end: &str, n: usize) -> Vec<String> {
+    let (r1,g1,b1) = parse_hex(start);
+    let (r2,g2,b2) = parse_hex(end);
+    let mix = |a: u8, b: u8, t: f64| (a as f64 + t*(b as f64 - a as f64)).round() as u8;
+    (0..n).map(|i| {
+        let t = if n > 1 {i as f64/(n - 1) as f64} else {0.0};
+        format!("#{:02x}{:02x}{:02x}",mix(r1,r2,t),mix(g1,g2,t),mix(b1,b2,t))
+    }).collect()
+}
@@ -97,35 +97,100 @@ use typed_arena::Arena;
 extern crate json;
 use json::JsonValue;
 
+#[cfg(feature = "flate2")]
+extern crate flate2;
+
 use std::io;
 use std::io::Write;
+use std::fmt;
+use std::error::Error;
+use std::collections::{HashMap,HashSet};
+
+pub mod colors;
 
 /// Iterator type for floating-point range iterator
 pub struct FRange {
-    val: f64,
+    start: f64,
     end: f64,
-    incr: f64
+    incr: f64,
+    idx: usize,
 }
 
 /// generates an iterator between `x1` and `x2`, step `skip`
 /// over floating point numbers.
 /// Similar to `linspace` in the **itertools-num** crate
 pub fn range(x1: f64, x2: f64, skip: f64) -> FRange {
-    FRange {val: x1, end: x2, incr: skip}
+    FRange {start: x1, end: x2, incr: skip, idx: 0}
 }
 
 impl Iterator for FRange {
     type Item = f64;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let res = self.val;
+        // computed from the start each time, so rounding errors don't add up
+        let res = self.start + self.idx as f64 * self.incr;
         if res >= self.end {
             None
         } else {
-            self.val += self.incr;
+            self.idx += 1;
+            Some(res)
+        }
+    }
+}
+
+/// Iterator type for a fixed number of evenly spaced floating-point values
+pub struct FSpace {
+    start: f64,
+    end: f64,
+    step: f64,
+    idx: usize,
+    n: usize,
+}
+
+/// generates exactly `n` evenly spaced values from `x1` to `x2`,
+/// including both endpoints.
+/// Like `linspace` in NumPy
+pub fn range_n(x1: f64, x2: f64, n: usize) -> FSpace {
+    let step = if n > 1 {(x2 - x1)/(n - 1) as f64} else {0.0};
+    FSpace {start: x1, end: x2, step, idx: 0, n}
+}
+
+/// generates exactly `n` evenly spaced values from `x1` to `x2`,
+/// the last being exactly `x2`. The same as `range_n`.
+pub fn linspace(x1: f64, x2: f64, n: usize) -> FSpace {
+    range_n(x1,x2,n)
+}
+
+impl Iterator for FSpace {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.n {
+            None
+        } else {
+            // make sure the last value is exactly the end
+            let res = if self.idx > 0 && self.idx == self.n - 1 {
+                self.end
+            } else {
+                self.start + self.idx as f64 * self.step
+            };
+            self.idx += 1;
             Some(res)
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.n - self.idx;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for FSpace {}
+
+/// generates `n` values from `10^start_exp` to `10^end_exp`, evenly spaced
+/// in the exponent. Like `logspace` in NumPy; useful with log axes.
+pub fn logspace(start_exp: f64, end_exp: f64, n: usize) -> Box<Iterator<Item=f64>> {
+    Box::new(range_n(start_exp,end_exp,n).map(|e| 10.0f64.powf(e)))
 }
 
 /// join two iterators of references together to produce point tuples.
@@ -137,6 +202,16 @@ where I1: IntoIterator<Item=&'a T1>+'a, I2: IntoIterator<Item=&'a T2>+'a,
     Box::new(x.into_iter().zip(y).map(|(&x,&y)| (x.into(),y.into())))
 }
 
+/// join three iterators of references together to produce tuples,
+/// such as points with errors for `Plot::points_with_errors`.
+/// The reference types can be anything that converts to `f64`
+pub fn zip3<'a,I1,I2,I3,T1,T2,T3>(x: I1, y: I2, z: I3) -> Box<Iterator<Item=(f64,f64,f64)>+'a>
+where I1: IntoIterator<Item=&'a T1>+'a, I2: IntoIterator<Item=&'a T2>+'a, I3: IntoIterator<Item=&'a T3>+'a,
+    T1: Into<f64>+Copy+'a, T2: Into<f64>+Copy+'a, T3: Into<f64>+Copy+'a
+{
+    Box::new(x.into_iter().zip(y).zip(z).map(|((&x,&y),&z)| (x.into(),y.into(),z.into())))
+}
+
 /// take an iterator of references to tuples of two types and produce point tuples.
 /// The types can be anything that converts to `f64`
 pub fn to_f64 <'a,I,T1,T2>(x: I) -> Box<Iterator<Item=(f64,f64)>+'a>
@@ -153,7 +228,13 @@ pub fn valr<'a,I,T>(y: I) -> Box<Iterator<Item=(f64,f64)>+'a>
 where I: IntoIterator<Item=&'a T>+'a,
     T: Into<f64>+Copy+'a
 {
-    Box::new((0..).into_iter().zip(y).map(|(x,&y)| (x.into(),y.into())))
+    indexed(y.into_iter().map(|&y| y.into()))
+}
+
+// values plotted against their index 0,1,2...
+fn indexed<'a,I>(y: I) -> Box<Iterator<Item=(f64,f64)>+'a>
+where I: IntoIterator<Item=f64>+'a {
+    Box::new((0..).zip(y).map(|(x,y): (u32,f64)| (x.into(),y)))
 }
 
 
@@ -166,6 +247,15 @@ where I: IntoIterator<Item=&'a T>+'a, F: Fn(f64)->f64 + 'a,
     Box::new(x.into_iter().map(move |&x| { let fv = x.into(); (fv,f(fv))}))
 }
 
+/// like `mapr`, but the function may return `None` for missing values,
+/// which become gaps with `Plot::lines_opt`
+pub fn mapr_opt<'a,I,T,F>(x: I, f: F) -> Box<Iterator<Item=Option<(f64,f64)>>+'a>
+where I: IntoIterator<Item=&'a T>+'a, F: Fn(f64)->Option<f64> + 'a,
+    T: Into<f64>+Copy+'a
+{
+    Box::new(x.into_iter().map(move |&x| { let fv = x.into(); f(fv).map(|y| (fv,y))}))
+}
+
 /// map an iterator of values with a function producing point tuples.
 /// The value type can be anything that converts to `f64`
 pub fn mapv<'a,I,T,F>(x: I, f: F) -> Box<Iterator<Item=(f64,f64)>+'a>
@@ -175,7 +265,235 @@ where I: IntoIterator<Item=T>+'a, F: Fn(f64)->f64 + 'a,
     Box::new(x.into_iter().map(move |x| { let fv = x.into(); (fv,f(fv))}))
 }
 
+/// numerical derivative of data sorted by x, using centered differences.
+/// The first and last points use one-sided differences, so all the
+/// x values are kept. x spacing does not need to be uniform.
+pub fn derivative(data: &[(f64,f64)]) -> Vec<(f64,f64)> {
+    let n = data.len();
+    if n < 2 {
+        return Vec::new();
+    }
+    let slope = |a: (f64,f64), b: (f64,f64)| (b.1 - a.1)/(b.0 - a.0);
+    (0..n).map(|i| {
+        let d = if i == 0 {
+            slope(data[0],data[1])
+        } else if i == n-1 {
+            slope(data[n-2],data[n-1])
+        } else {
+            slope(data[i-1],data[i+1])
+        };
+        (data[i].0,d)
+    }).collect()
+}
+
+/// cumulative integral of data sorted by x, using the trapezoidal rule.
+/// Starts at zero at the first x value.
+pub fn integral(data: &[(f64,f64)]) -> Vec<(f64,f64)> {
+    let mut sum = 0.0;
+    let mut res = Vec::with_capacity(data.len());
+    for (i,&(x,y)) in data.iter().enumerate() {
+        if i > 0 {
+            let (px,py) = data[i-1];
+            sum += 0.5*(y + py)*(x - px);
+        }
+        res.push((x,sum));
+    }
+    res
+}
+
+/// resample data onto new x values using linear interpolation.
+/// The data is sorted by x first. x values outside the range of
+/// the data are left out of the result, as are points and x values
+/// which are not finite.
+pub fn interpolate(data: &[(f64,f64)], new_x: &[f64]) -> Vec<(f64,f64)> {
+    let mut sorted: Vec<_> = data.iter().cloned()
+        .filter(|p| p.0.is_finite() && p.1.is_finite()).collect();
+    sorted.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut res = Vec::new();
+    if sorted.is_empty() {
+        return res;
+    }
+    let (first,last) = (sorted[0].0, sorted[sorted.len()-1].0);
+    for &x in new_x {
+        if ! x.is_finite() || x < first || x > last {
+            continue;
+        }
+        // first point with an x value not less than x
+        let i = sorted.partition_point(|p| p.0 < x);
+        let (x2,y2) = match sorted.get(i) {
+            Some(&p) => p,
+            None => continue
+        };
+        let y = if x2 == x || i == 0 {
+            y2
+        } else {
+            let (x1,y1) = sorted[i-1];
+            y1 + (y2 - y1)*(x - x1)/(x2 - x1)
+        };
+        res.push((x,y));
+    }
+    res
+}
+
+/// the difference `a - b` at the x values of `a`, interpolating `b`
+/// onto them. Only x values in the range of both series are kept.
+pub fn difference(a: &[(f64,f64)], b: &[(f64,f64)]) -> Vec<(f64,f64)> {
+    let xs: Vec<_> = a.iter().map(|p| p.0).collect();
+    let mut bi = interpolate(b,&xs).into_iter().peekable();
+    let mut res = Vec::new();
+    for &(x,y) in a {
+        // interpolate keeps the order of the x values, leaving some out
+        if let Some(&(bx,by)) = bi.peek() {
+            if bx == x {
+                res.push((x,y - by));
+                bi.next();
+            }
+        }
+    }
+    res
+}
+
+/// split data into the points for which `pred(x,y)` is true and those
+/// for which it is false, e.g. to plot outliers as a separate series.
+/// This only splits the data; the order of the points is kept.
+#[allow(clippy::type_complexity)]
+pub fn partition<F>(data: &[(f64,f64)], pred: F) -> (Vec<(f64,f64)>,Vec<(f64,f64)>)
+where F: Fn(f64,f64)->bool {
+    data.iter().partition(|p| pred(p.0,p.1))
+}
+
+// points sorted by x, grouped by x value. x values within a relative
+// difference of 1e-9 of the first x in a group belong to that group.
+fn group_by_x(data: &[(f64,f64)]) -> Vec<(f64,Vec<f64>)> {
+    let mut sorted = data.to_vec();
+    sorted.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut res: Vec<(f64,Vec<f64>)> = Vec::new();
+    for (x,y) in sorted {
+        if let Some(g) = res.last_mut() {
+            if (x - g.0).abs() <= 1e-9*g.0.abs().max(1.0) {
+                g.1.push(y);
+                continue;
+            }
+        }
+        res.push((x,vec![y]));
+    }
+    res
+}
+
+/// combine points with the same x value by adding their y values,
+/// e.g. to make bars from individual transactions. The result is
+/// sorted by x. x values which differ by less than one part in 10^9
+/// count as the same.
+pub fn group_sum(data: &[(f64,f64)]) -> Vec<(f64,f64)> {
+    group_by_x(data).into_iter().map(|(x,ys)| (x,ys.iter().sum())).collect()
+}
+
+/// combine points with the same x value by averaging their y values.
+/// Like `group_sum`, the result is sorted by x.
+pub fn group_mean(data: &[(f64,f64)]) -> Vec<(f64,f64)> {
+    group_by_x(data).into_iter().map(|(x,ys)| (x,ys.iter().sum::<f64>()/ys.len() as f64)).collect()
+}
+
+/// statistic computed by `rolling` over each window
+pub enum RollingStat {
+    /// average of the values
+    Mean,
+    /// smallest value
+    Min,
+    /// largest value
+    Max,
+    /// standard deviation of the values
+    Std,
+}
+
+/// a statistic of the y values in a window of `window` points sliding
+/// along the data. Each result has the x value of the last point
+/// in its window, so there are `window - 1` fewer points than in `data`.
+/// Rolling minimum and maximum give an envelope around a rolling mean.
+pub fn rolling(data: &[(f64,f64)], window: usize, stat: RollingStat) -> Vec<(f64,f64)> {
+    if window == 0 {
+        return Vec::new();
+    }
+    data.windows(window).map(|w| {
+        let ys = w.iter().map(|p| p.1);
+        let n = window as f64;
+        let mean = ys.clone().sum::<f64>()/n;
+        let v = match stat {
+            RollingStat::Mean => mean,
+            RollingStat::Min => ys.fold(f64::INFINITY,f64::min),
+            RollingStat::Max => ys.fold(f64::NEG_INFINITY,f64::max),
+            RollingStat::Std => (ys.map(|y| (y - mean)*(y - mean)).sum::<f64>()/n).sqrt(),
+        };
+        (w[window-1].0,v)
+    }).collect()
+}
+
+/// count values into `bins` equal bins spanning their range.
+/// Returns the left edge of each bin with its count, and the bin width.
+/// Non-finite values are ignored.
+pub fn histogram<T>(values: T, bins: usize) -> (Vec<(f64,f64)>,f64)
+where T: IntoIterator<Item=f64> {
+    let values: Vec<_> = values.into_iter().filter(|v| v.is_finite()).collect();
+    if values.is_empty() || bins == 0 {
+        return (Vec::new(),1.0);
+    }
+    let lo = values.iter().cloned().fold(f64::INFINITY,f64::min);
+    let hi = values.iter().cloned().fold(f64::NEG_INFINITY,f64::max);
+    let width = if hi > lo {(hi - lo)/bins as f64} else {1.0};
+    let mut counts = vec![0.0; bins];
+    for v in values {
+        // the maximum value goes in the last bin
+        let i = ((v - lo)/width) as usize;
+        counts[i.min(bins - 1)] += 1.0;
+    }
+    let res = counts.into_iter().enumerate()
+        .map(|(i,c)| (lo + i as f64*width, c)).collect();
+    (res,width)
+}
+
+/// read a CSV file where column `x_col` holds the x values and every
+/// other column is a separate series. The first line gives the column
+/// labels. Returns the labels of the series and their data; empty cells
+/// are skipped.
+#[allow(clippy::type_complexity)]
+pub fn read_csv_wide(path: &str, x_col: usize) -> io::Result<(Vec<String>,Vec<Vec<(f64,f64)>>)> {
+    use std::io::BufRead;
+    let bad = |line: usize, msg: String| io::Error::new(io::ErrorKind::InvalidData,
+        format!("{}:{}: {}",path,line,msg));
+    let reader = io::BufReader::new(std::fs::File::open(path)?);
+    let mut lines = reader.lines();
+    let header = match lines.next() {
+        Some(line) => line?,
+        None => return Err(bad(1,"empty file".into()))
+    };
+    let header: Vec<_> = header.split(',').map(|s| s.trim().to_string()).collect();
+    if x_col >= header.len() {
+        return Err(bad(1,format!("no column {}",x_col)));
+    }
+    let labels: Vec<_> = header.iter().enumerate()
+        .filter(|&(i,_)| i != x_col).map(|(_,l)| l.clone()).collect();
+    let mut data = vec![Vec::new(); labels.len()];
+    for (i,line) in lines.enumerate() {
+        let line = line?;
+        let lineno = i + 2;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cells: Vec<_> = line.split(',').map(|s| s.trim()).collect();
+        let parse = |s: &str| s.parse::<f64>().map_err(|_| bad(lineno,format!("bad number {:?}",s)));
+        let x = parse(cells.get(x_col).cloned().unwrap_or(""))?;
+        let ys = cells.iter().enumerate().filter(|&(j,_)| j != x_col).map(|(_,c)| c);
+        for (series,cell) in data.iter_mut().zip(ys) {
+            if ! cell.is_empty() {
+                series.push((x,parse(cell)?));
+            }
+        }
+    }
+    Ok((labels,data))
+}
+
 
+#[derive(PartialEq,Clone)]
 enum PlotKind {
     Lines,
     Points,
@@ -192,41 +510,330 @@ impl PlotKind {
     }
 }
 
+// ids are used in jQuery selectors, so only simple names are allowed
+fn check_id(id: &str) {
+    if id.is_empty() || ! id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        panic!("{:?} is not a valid id: use letters, digits, '-' and '_'",id);
+    }
+}
+
+// escape the characters that are significant in HTML text
+fn escape_html(txt: &str) -> String {
+    let mut escaped = String::new();
+    for ch in txt.chars() {
+        match ch {
+        '<' => escaped.push_str("&lt;"),
+        '>' => escaped.push_str("&gt;"),
+        '&' => escaped.push_str("&amp;"),
+        _ => escaped.push(ch)
+        }
+    }
+    escaped
+}
+
+// the text of some HTML, with any tags removed
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for ch in html.chars() {
+        match ch {
+        '<' => in_tag = true,
+        '>' if in_tag => in_tag = false,
+        _ if ! in_tag => text.push(ch),
+        _ => ()
+        }
+    }
+    text
+}
+
+/// problems found in series data by `Plot::try_lines` and friends
+#[derive(Debug)]
+pub enum DataError {
+    /// the series with this label has no data
+    Empty(String),
+    /// the series has a NaN or infinite value at this index
+    NonFinite(String,usize,(f64,f64)),
+    /// stacked series do not share these x values
+    Misaligned(Vec<f64>),
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataError::Empty(ref label) => write!(f,"no data to plot for {:?}",label),
+            DataError::NonFinite(ref label,i,p) =>
+                write!(f,"series {:?} has a non-finite point {:?} at index {}",label,p,i),
+            DataError::Misaligned(ref xs) =>
+                write!(f,"stacked series do not all have values at x = {:?}",xs),
+        }
+    }
+}
+
+impl Error for DataError {
+    fn description(&self) -> &str {
+        match *self {
+            DataError::Empty(_) => "no data to plot",
+            DataError::NonFinite(..) => "non-finite data point",
+            DataError::Misaligned(_) => "stacked series have different x values",
+        }
+    }
+}
+
+/// a `Series` method was used on a kind of series it does not apply to,
+/// from `Series::try_radius` and friends
+#[derive(Debug)]
+pub enum SeriesError {
+    /// the method was called on the wrong kind of series
+    WrongKind {
+        method: &'static str,
+        expected: &'static str,
+        actual: &'static str,
+    },
+}
+
+impl fmt::Display for SeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SeriesError::WrongKind{method,expected,actual} =>
+                write!(f,"{}() only applies to {}, not {}",method,expected,actual),
+        }
+    }
+}
+
+impl Error for SeriesError {
+    fn description(&self) -> &str {
+        match *self {
+            SeriesError::WrongKind{..} => "method does not apply to this kind of series",
+        }
+    }
+}
+
+// largest-triangle-three-buckets downsampling to `threshold` points,
+// which keeps the first and last points and the visual shape
+fn lttb(data: &[(f64,f64)], threshold: usize) -> Vec<(f64,f64)> {
+    let n = data.len();
+    if threshold >= n {
+        return data.to_vec();
+    }
+    if threshold < 3 {
+        return vec![data[0],data[n-1]].into_iter().take(threshold).collect();
+    }
+    let every = (n - 2) as f64/(threshold - 2) as f64;
+    let mut res = vec![data[0]];
+    let mut a = 0;
+    for i in 0..threshold-2 {
+        // average of the next bucket
+        let avg_start = ((i + 1) as f64*every) as usize + 1;
+        let avg_end = (((i + 2) as f64*every) as usize + 1).min(n);
+        let next = &data[avg_start..avg_end];
+        let count = next.len() as f64;
+        let avg_x = next.iter().map(|p| p.0).sum::<f64>()/count;
+        let avg_y = next.iter().map(|p| p.1).sum::<f64>()/count;
+        // the point in this bucket making the largest triangle
+        let start = (i as f64*every) as usize + 1;
+        let end = ((i + 1) as f64*every) as usize + 1;
+        let (ax,ay) = data[a];
+        let mut best = start;
+        let mut best_area = -1.0;
+        for (j,&(x,y)) in data.iter().enumerate().take(end).skip(start) {
+            let area = ((ax - avg_x)*(y - ay) - (ax - x)*(avg_y - ay)).abs();
+            if area > best_area {
+                best_area = area;
+                best = j;
+            }
+        }
+        res.push(data[best]);
+        a = best;
+    }
+    res.push(data[n-1]);
+    res
+}
+
+// x values as hash keys, so series can be matched up point by point;
+// adding zero turns -0.0 into 0.0
+fn x_key(x: f64) -> u64 {
+    (x + 0.0).to_bits()
+}
+
+// the smallest of 1, 2 or 5 times a power of ten which is at least `x`
+fn nice_step(x: f64) -> f64 {
+    let p = 10f64.powf(x.log10().floor());
+    [1.0,2.0,5.0,10.0].iter().map(|m| m*p).find(|&s| s >= x).unwrap_or(10.0*p)
+}
+
+// monotone cubic (Fritsch-Carlson) interpolation through points in order
+// of x, with `per` points for each interval. The curve never overshoots
+// the data between points.
+fn monotone_cubic(pts: &[(f64,f64)], per: usize) -> Vec<(f64,f64)> {
+    let n = pts.len();
+    if n < 3 {
+        return pts.to_vec();
+    }
+    let h: Vec<_> = pts.windows(2).map(|w| w[1].0 - w[0].0).collect();
+    let d: Vec<_> = pts.windows(2).zip(&h).map(|(w,&h)| (w[1].1 - w[0].1)/h).collect();
+    let mut m = vec![0.0; n];
+    m[0] = d[0];
+    m[n-1] = d[n-2];
+    for k in 1..n-1 {
+        m[k] = if d[k-1]*d[k] <= 0.0 {0.0} else {0.5*(d[k-1] + d[k])};
+    }
+    for k in 0..n-1 {
+        if d[k] == 0.0 {
+            m[k] = 0.0;
+            m[k+1] = 0.0;
+        } else {
+            let a = m[k]/d[k];
+            let b = m[k+1]/d[k];
+            let r = a*a + b*b;
+            if r > 9.0 {
+                let t = 3.0/r.sqrt();
+                m[k] = t*a*d[k];
+                m[k+1] = t*b*d[k];
+            }
+        }
+    }
+    let mut res = vec![pts[0]];
+    for k in 0..n-1 {
+        let ((x0,y0),(_,y1)) = (pts[k],pts[k+1]);
+        for i in 1..per+1 {
+            let t = i as f64/per as f64;
+            let (t2,t3) = (t*t,t*t*t);
+            let y = (2.0*t3 - 3.0*t2 + 1.0)*y0 + (t3 - 2.0*t2 + t)*h[k]*m[k]
+                + (-2.0*t3 + 3.0*t2)*y1 + (t3 - t2)*h[k]*m[k+1];
+            res.push((x0 + t*h[k],y));
+        }
+    }
+    res
+}
+
+// adaptive sampling: add points after `a` up to and including `b`,
+// splitting the interval while the function is not close to a straight line
+fn subdivide<F>(f: &F, a: (f64,f64), b: (f64,f64), tolerance: f64, depth: u32, points: &mut Vec<(f64,f64)>)
+where F: Fn(f64)->f64 {
+    let mx = 0.5*(a.0 + b.0);
+    let m = (mx,f(mx));
+    // a NaN deviation also counts as not close
+    let dev = (m.1 - 0.5*(a.1 + b.1)).abs();
+    if depth > 0 && (dev > tolerance || dev.is_nan()) {
+        subdivide(f,a,m,tolerance,depth-1,points);
+        subdivide(f,m,b,tolerance,depth-1,points);
+    } else {
+        points.push(b);
+    }
+}
+
 /// describes a data series which can be plotted either as lines, points or bars
+#[derive(Clone)]
 pub struct Series {
     data: JsonValue,
     kind: PlotKind,
     symbols: bool,
+    hidden: bool,
+    fill_to: Option<f64>,
+    candles: Option<Candles>,
+    band: Option<Vec<(f64,f64)>>,
+    last_value: bool,
+    precision: Option<usize>,
+}
+
+// open-high-low-close data drawn as markings
+#[derive(Clone)]
+struct Candles {
+    data: Vec<(f64,f64,f64,f64,f64)>,
+    up: String,
+    down: String,
 }
 
 impl Series {
     fn new<T>(kind: PlotKind, label: &str, data: T) -> Series
     where T: IntoIterator<Item=(f64,f64)> {
 
-        let mut arr = JsonValue::new_array();
-        for p in data.into_iter() {
-            arr.push(array![p.0,p.1]).unwrap();
-        }
         let jlbl = if label.is_empty() {JsonValue::Null} else {label.into()};
-        let mut data = object! {
+        let mut obj = object! {
             "label" => jlbl,
-            "data" => arr
+            "data" => array![]
+        };
+        obj[kind.to_str()] = object!{"show" => true};
+        let mut series = Series {
+            data: obj, kind, symbols: false, hidden: false,
+            fill_to: None, candles: None, band: None, last_value: false,
+            precision: None
         };
-        data[kind.to_str()] = object!{"show" => true};
-        Series {data: data, kind: kind, symbols: false}
+        series.extend(data);
+        series
+    }
+
+    /// the JSON object describing this series, as it will be given to Flot.
+    pub fn as_json(&self) -> &JsonValue {
+        &self.data
+    }
+
+    /// append a point to the series data.
+    pub fn push(&mut self, x: f64, y: f64) -> &mut Self {
+        self.data["data"].push(array![x,y]).unwrap();
+        self
+    }
+
+    /// append points from anything that converts to an iterator
+    /// of `(f64,f64)` tuples.
+    pub fn extend<T>(&mut self, data: T) -> &mut Self
+    where T: IntoIterator<Item=(f64,f64)> {
+        for p in data.into_iter() {
+            self.push(p.0,p.1);
+        }
+        self
     }
 
     fn kind_ref(&mut self) -> &mut JsonValue {
         &mut self.data[self.kind.to_str()]
     }
 
+    // points drawn by our own hook. Flot only finds items for hover and
+    // clicks in series that it draws, so it draws them with nothing to see.
+    fn invisible_points(&mut self) {
+        self.kind_ref()["lineWidth"] = 0.into();
+        self.kind_ref()["fill"] = false.into();
+        self.data["shadowSize"] = 0.into();
+    }
+
+    /// whether the series is initially shown. A hidden series is left
+    /// out of the plot, but with `Plot::toggleable_legend` it keeps its
+    /// legend entry and can be shown by clicking on it.
+    pub fn visible(&mut self, show: bool) -> &mut Self {
+        self.hidden = ! show;
+        self
+    }
+
+    /// append the y value of the last point to the label, so that
+    /// the legend reads like "Temperature (23.4)".
+    pub fn show_last_value(&mut self) -> &mut Self {
+        self.last_value = true;
+        self
+    }
+
+    /// set the id of this series, so that other series and Flot plugins
+    /// can refer to it. Like `Plot::id`, it must be unique within the page.
+    pub fn id(&mut self, id: &str) -> &mut Self {
+        check_id(id);
+        self.data["id"] = id.into();
+        self
+    }
+
+    /// round the data to `decimals` decimal places when rendering,
+    /// which can make the output much smaller for large series.
+    pub fn precision(&mut self, decimals: usize) -> &mut Self {
+        self.precision = Some(decimals);
+        self
+    }
+
     /// set the xaxis for this series (2 for second)
     pub fn xaxis(&mut self, which: u32) -> &mut Self {
         self.data["xaxis"] = which.into();
         self
     }
 
-    /// set the yaxis for this series (2 for second)
+    /// set the yaxis for this series (2 for second).
+    /// The axis is created when rendering if it has not been configured
     pub fn yaxis(&mut self, which: u32) -> &mut Self {
         self.data["yaxis"] = which.into();
         self
@@ -238,6 +845,32 @@ impl Series {
         self
     }
 
+    /// fill the area between the line and the level `y` (lines only).
+    /// Uses the fillbetween plugin with a hidden series at the baseline.
+    pub fn fill_to(&mut self, y: f64, opacity: f32) -> &mut Self {
+        if let Err(e) = self.expect_kind("fill_to",PlotKind::Lines) {
+            panic!("{}",e);
+        }
+        self.fill_to = Some(y);
+        self.fill(opacity)
+    }
+
+    /// colours of rising and falling candles (candlesticks only)
+    pub fn candle_colors(&mut self, up: &str, down: &str) -> &mut Self {
+        match self.candles {
+            Some(ref mut c) => {
+                c.up = up.into();
+                c.down = down.into();
+            },
+            None => panic!("{}",SeriesError::WrongKind {
+                method: "candle_colors",
+                expected: "candlesticks",
+                actual: self.kind.to_str(),
+            })
+        }
+        self
+    }
+
     /// set the fill colour underneath lines or in bars as an HTML colour.
     pub fn fill_color(&mut self, color: &str) -> &mut Self {
         self.kind_ref()["fillColor"] = color.into();
@@ -256,47 +889,288 @@ impl Series {
         self
     }
 
-    /// radius for points (points only)
+    /// radius for points (points only).
+    /// This also sets the size of any symbol, which is scaled to match
+    /// the area of a circle with this radius
     pub fn radius(&mut self, size: u32) -> &mut Self {
-        match self.kind {
-            PlotKind::Points => self.kind_ref()["radius"] = size.into(),
-            _ => panic!("radius() only applies to points")
+        match self.try_radius(size) {
+            Ok(s) => s,
+            Err(e) => panic!("{}",e)
         }
-        self
     }
 
-    /// symbol for points (points only)
+    /// like `radius`, but an error for series which are not points.
+    pub fn try_radius(&mut self, size: u32) -> Result<&mut Self,SeriesError> {
+        self.expect_kind("radius",PlotKind::Points)?;
+        self.kind_ref()["radius"] = size.into();
+        Ok(self)
+    }
+
+    /// symbol for points (points only): "circle", "square", "diamond",
+    /// "triangle" or "cross". Anything but circle loads the symbol plugin
     pub fn symbol(&mut self, name: &str) -> &mut Self {
-        match self.kind {
-            PlotKind::Points => {
-                self.symbols = true;
-                self.kind_ref()["symbol"] = name.into();
-            },
-            _ => panic!("symbol() only applies to points")
+        match self.try_symbol(name) {
+            Ok(s) => s,
+            Err(e) => panic!("{}",e)
+        }
+    }
+
+    /// like `symbol`, but an error for series which are not points.
+    pub fn try_symbol(&mut self, name: &str) -> Result<&mut Self,SeriesError> {
+        self.expect_kind("symbol",PlotKind::Points)?;
+        self.symbols = name != "circle";
+        self.kind_ref()["symbol"] = name.into();
+        Ok(self)
+    }
+
+    /// give each point its own colour, in order; points without
+    /// a colour use the series colour (points only).
+    pub fn point_colors(&mut self, colors: &[&str]) -> &mut Self {
+        match self.try_point_colors(colors) {
+            Ok(s) => s,
+            Err(e) => panic!("{}",e)
+        }
+    }
+
+    /// like `point_colors`, but an error for series which are not points.
+    pub fn try_point_colors(&mut self, colors: &[&str]) -> Result<&mut Self,SeriesError> {
+        self.expect_kind("point_colors",PlotKind::Points)?;
+        self.data["pointColors"] = colors.to_vec().into();
+        self.invisible_points();
+        Ok(self)
+    }
+
+    fn expect_kind(&self, method: &'static str, kind: PlotKind) -> Result<(),SeriesError> {
+        if self.kind == kind {
+            Ok(())
+        } else {
+            Err(SeriesError::WrongKind {
+                method,
+                expected: kind.to_str(),
+                actual: self.kind.to_str(),
+            })
+        }
+    }
+
+    /// stack this series with the other series in the same group.
+    /// The group may be an integer or a string; series in different
+    /// groups are stacked independently. Series in a group must
+    /// share x values.
+    pub fn stack<T: Into<JsonValue>>(&mut self, group: T) -> &mut Self {
+        self.data["stack"] = group.into();
+        self
+    }
+
+    /// draw a smooth curve through the points which never overshoots
+    /// the data, so monotone data like cumulative counts stays monotone.
+    /// The curve is worked out here, so each interval between points
+    /// becomes eight points in the output. The data should be in order of x.
+    pub fn smooth_monotone(&mut self) -> &mut Self {
+        let mut res = Vec::new();
+        let mut segment = Vec::new();
+        for p in self.points() {
+            match p {
+                Some(p) => segment.push(p),
+                None => {
+                    res.extend(monotone_cubic(&segment,8).into_iter().map(Some));
+                    res.push(None);
+                    segment.clear();
+                }
+            }
+        }
+        res.extend(monotone_cubic(&segment,8).into_iter().map(Some));
+        self.set_points(res);
+        self
+    }
+
+    /// reduce the series to about `max_points` points, keeping its visual
+    /// shape, for series too large to render comfortably. Uses the
+    /// largest-triangle-three-buckets method; gaps are kept. Does nothing
+    /// if the series is already small enough.
+    pub fn decimate(&mut self, max_points: usize) -> &mut Self {
+        let points = self.points();
+        if points.len() <= max_points {
+            return self;
+        }
+        let mut segments = vec![Vec::new()];
+        for p in points {
+            match p {
+                Some(p) => segments.last_mut().unwrap().push(p),
+                None => segments.push(Vec::new())
+            }
+        }
+        let total: usize = segments.iter().map(|s| s.len()).sum();
+        let mut res = Vec::new();
+        for (i,segment) in segments.iter().enumerate() {
+            if i > 0 {
+                res.push(None);
+            }
+            let n = (segment.len()*max_points/total.max(1)).max(2);
+            res.extend(lttb(segment,n).into_iter().map(Some));
+        }
+        self.set_points(res);
+        self
+    }
+
+    /// break the line at these x values, even where there is data.
+    /// Gaps are inserted after any points at or before each x value,
+    /// so the data should be in order of x. x values which are not
+    /// finite are ignored.
+    pub fn break_at(&mut self, xs: &[f64]) -> &mut Self {
+        let mut breaks: Vec<f64> = xs.iter().cloned().filter(|x| x.is_finite()).collect();
+        breaks.sort_by(|a,b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let mut breaks = breaks.into_iter().peekable();
+        let mut res = Vec::new();
+        for p in self.points() {
+            if let Some((x,_)) = p {
+                while breaks.peek().is_some_and(|&b| b < x) {
+                    breaks.next();
+                    res.push(None);
+                }
+            }
+            res.push(p);
         }
+        self.set_points(res);
         self
     }
 
+    /// draw a dashed line, with `pattern` giving the lengths in pixels
+    /// of the dashes and the spaces between them, e.g. `&[5,3]` (lines only).
+    /// Uses the dashes plugin, which is not distributed with Flot,
+    /// so it must be in the `FLOT` directory or the `assets_base` location.
+    /// The line width is passed on to the dashed line, and any fill is
+    /// still drawn by Flot.
+    pub fn dashes(&mut self, pattern: &[u32]) -> &mut Self {
+        match self.try_dashes(pattern) {
+            Ok(s) => s,
+            Err(e) => panic!("{}",e)
+        }
+    }
+
+    /// like `dashes`, but an error for series which are not lines.
+    pub fn try_dashes(&mut self, pattern: &[u32]) -> Result<&mut Self,SeriesError> {
+        self.expect_kind("dashes",PlotKind::Lines)?;
+        self.data["dashes"] = object!{
+            "show" => true,
+            "dashLength" => pattern.to_vec()
+        };
+        Ok(self)
+    }
+
     /// draw steps between points (lines only)
     pub fn steps(&mut self) -> &mut Self {
-        match self.kind {
-            PlotKind::Lines => self.kind_ref()["steps"] = true.into(),
-            _ => panic!("steps() only applies to lines")
+        match self.try_steps() {
+            Ok(s) => s,
+            Err(e) => panic!("{}",e)
         }
+    }
+
+    /// like `steps`, but an error for series which are not lines.
+    pub fn try_steps(&mut self) -> Result<&mut Self,SeriesError> {
+        self.expect_kind("steps",PlotKind::Lines)?;
+        self.kind_ref()["steps"] = true.into();
+        Ok(self)
+    }
+
+    /// draw steps between points, choosing where the change in value
+    /// happens (lines only). The data is expanded into the stepped shape.
+    pub fn steps_direction(&mut self, dir: StepDirection) -> &mut Self {
+        if let Err(e) = self.expect_kind("steps_direction",PlotKind::Lines) {
+            panic!("{}",e);
+        }
+        let mut res = Vec::new();
+        let mut last: Option<(f64,f64)> = None;
+        for p in self.points() {
+            if let (Some((x0,y0)),Some((x,y))) = (last,p) {
+                match dir {
+                    StepDirection::Before => res.push(Some((x0,y))),
+                    StepDirection::After => res.push(Some((x,y0))),
+                    StepDirection::Center => {
+                        let m = 0.5*(x0 + x);
+                        res.push(Some((m,y0)));
+                        res.push(Some((m,y)));
+                    }
+                }
+            }
+            res.push(p);
+            last = p;
+        }
+        self.set_points(res);
         self
     }
 
+    // the data points, with None for gaps
+    fn points(&self) -> Vec<Option<(f64,f64)>> {
+        self.data["data"].members().map(|p| {
+            match (p[0].as_f64(),p[1].as_f64()) {
+                (Some(x),Some(y)) => Some((x,y)),
+                _ => None
+            }
+        }).collect()
+    }
+
+    fn set_points(&mut self, points: Vec<Option<(f64,f64)>>) {
+        let mut arr = JsonValue::new_array();
+        for p in points {
+            arr.push(match p {
+                Some((x,y)) => array![x,y],
+                None => JsonValue::Null
+            }).unwrap();
+        }
+        self.data["data"] = arr;
+    }
+
     /// set width of bars (bars only)
     pub fn width(&mut self, width: f64) -> &mut Self {
-        match self.kind {
-            PlotKind::Bars => self.kind_ref()["barWidth"] = width.into(),
-            _ => panic!("bar_width() only applies to bars")
+        match self.try_width(width) {
+            Ok(s) => s,
+            Err(e) => panic!("{}",e)
         }
-        self
     }
 
+    /// like `width`, but an error for series which are not bars.
+    pub fn try_width(&mut self, width: f64) -> Result<&mut Self,SeriesError> {
+        self.expect_kind("width",PlotKind::Bars)?;
+        self.kind_ref()["barWidth"] = width.into();
+        Ok(self)
+    }
+
+
+
+}
 
+/// default settings for all the series in a plot.
+/// Fields left as `None` keep Flot's defaults.
+///
+/// ```rust,ignore
+/// p.series_defaults(SeriesDefaults {
+///     line_width: Some(1),
+///     shadow_size: Some(0),
+///     ..Default::default()
+/// });
+/// ```
+#[derive(Default)]
+pub struct SeriesDefaults {
+    /// width of lines
+    pub line_width: Option<u32>,
+    /// radius of points
+    pub point_radius: Option<u32>,
+    /// width of bars, in x axis units
+    pub bar_width: Option<f64>,
+    /// size of shadows; zero for none
+    pub shadow_size: Option<u32>,
+    /// fill opacity for lines and bars
+    pub fill: Option<f32>,
+}
 
+/// where the change in value happens for stepped lines
+pub enum StepDirection {
+    /// the value changes at the previous point
+    Before,
+    /// the value is held until the next point
+    After,
+    /// the value changes halfway between points
+    Center,
 }
 
 /// describes position of legend (None for no legend)
@@ -321,7 +1195,52 @@ impl Corner {
     }
 }
 
+/// describes how values are mapped to colours in a heatmap
+pub enum ColorScale {
+    /// white for the lowest values through to black
+    Gray,
+    /// blue for the lowest values through to red
+    BlueRed,
+    /// interpolate between two RGB colours
+    Rgb((u8,u8,u8),(u8,u8,u8)),
+}
+
+impl ColorScale {
+    // colour for a value between 0 and 1
+    fn color(&self, t: f64) -> String {
+        use ColorScale::*;
+        let (lo,hi) = match *self {
+            Gray => ((255,255,255),(0,0,0)),
+            BlueRed => ((0,0,255),(255,0,0)),
+            Rgb(lo,hi) => (lo,hi),
+        };
+        let mix = |a: u8, b: u8| (a as f64 + t*(b as f64 - a as f64)).round() as u8;
+        format!("#{:02x}{:02x}{:02x}",mix(lo.0,hi.0),mix(lo.1,hi.1),mix(lo.2,hi.2))
+    }
+}
+
+/// subtle patterns for the plot background
+#[derive(Clone)]
+pub enum BackgroundPattern {
+    Dots,
+    DiagonalLines,
+    Crosshatch,
+}
+
+impl BackgroundPattern {
+    fn to_css(&self) -> &'static str {
+        use BackgroundPattern::*;
+        match *self {
+            Dots => "background-image:radial-gradient(#bbb 1px, transparent 1px);background-size:8px 8px",
+            DiagonalLines => "background-image:repeating-linear-gradient(45deg, #ddd 0, #ddd 1px, transparent 1px, transparent 8px)",
+            Crosshatch => "background-image:repeating-linear-gradient(45deg, #ddd 0, #ddd 1px, transparent 1px, transparent 8px),\
+                repeating-linear-gradient(-45deg, #ddd 0, #ddd 1px, transparent 1px, transparent 8px)",
+        }
+    }
+}
+
 /// describes sides of plot for axis position
+#[derive(Clone)]
 pub enum Side {
     Right,
     Left,
@@ -350,15 +1269,78 @@ pub struct Axis<'a> {
 
 const TICK_FORMAT: &str = "v.toFixed(a.tickDecimals)";
 
-impl <'a> Axis<'a> {
-    fn new(which: &'static str, plot: &'a mut Plot, idx: usize) -> Axis<'a> {
-        if plot.options[which].is_null() {
-            plot.options[which] = array![object!{}];
-        }
-        if idx > 1 {
-            plot.options[which].push(object!{}).unwrap();
-        }
-        Axis{which: which, plot: plot, idx: idx-1}
+// how the default tick labels of an axis are modified
+#[derive(Clone)]
+struct TickFormat {
+    pre: String,
+    post: String,
+    offset: f64,
+    scale: f64,
+}
+
+impl TickFormat {
+    fn new() -> TickFormat {
+        TickFormat {pre: String::new(), post: String::new(), offset: 0.0, scale: 1.0}
+    }
+
+    fn function(&self) -> String {
+        let value = if self.offset == 0.0 && self.scale == 1.0 {
+            TICK_FORMAT.to_string()
+        } else {
+            // scaling down the values needs more decimal places
+            format!("((v - {}) * {}).toFixed(a.tickDecimals + Math.max(0,-Math.floor(Math.log({})/Math.LN10)))",
+                self.offset,self.scale,self.scale)
+        };
+        format!("function(v,a) {{ return {:?} + {} + {:?}; }}",self.pre,value,self.post)
+    }
+}
+
+// a logarithmic axis, whose ticks are worked out when rendering
+#[derive(Clone)]
+struct LogAxis {
+    base: f64,
+    minor: bool,
+}
+
+impl LogAxis {
+    // labelled ticks at each power of the base, and optionally
+    // unlabelled minor ticks at the multiples in between
+    fn ticks(&self, lo: f64, hi: f64) -> JsonValue {
+        let b = self.base;
+        let (k1,k2) = (lo.log(b).floor() as i32, hi.log(b).ceil() as i32);
+        let mut arr = JsonValue::new_array();
+        for k in k1..k2+1 {
+            let v = b.powi(k);
+            let label = if k < 0 && b == 10.0 {
+                format!("{:.*}",(-k) as usize,v)
+            } else {
+                format!("{}",v)
+            };
+            arr.push(array![v,label]).unwrap();
+            if self.minor && k < k2 {
+                let mut m = 2.0;
+                while m < b {
+                    arr.push(array![m*v,""]).unwrap();
+                    m += 1.0;
+                }
+            }
+        }
+        arr
+    }
+}
+
+impl <'a> Axis<'a> {
+    fn new(which: &'static str, plot: &'a mut Plot, idx: usize) -> Axis<'a> {
+        if idx == 0 {
+            panic!("axes are numbered from 1, not 0");
+        }
+        if plot.options[which].is_null() {
+            plot.options[which] = array![];
+        }
+        while plot.options[which].len() < idx {
+            plot.options[which].push(object!{}).unwrap();
+        }
+        Axis{which, plot, idx: idx-1}
     }
 
     pub fn set_option(&mut self, key: &str, val: JsonValue) -> &mut Self {
@@ -387,37 +1369,75 @@ impl <'a> Axis<'a> {
         self.axis_function("tickFormatter",fun)
     }
 
+    // update the tick format of this axis and regenerate its formatter
+    fn tick_format<F>(&mut self, update: F) -> &mut Self
+    where F: FnOnce(&mut TickFormat) {
+        let key = (self.which,self.idx);
+        let pos = match self.plot.tick_formats.iter().position(|t| t.0 == key) {
+            Some(pos) => pos,
+            None => {
+                self.plot.tick_formats.push((key,TickFormat::new()));
+                self.plot.tick_formats.len() - 1
+            }
+        };
+        update(&mut self.plot.tick_formats[pos].1);
+        let fun = self.plot.tick_formats[pos].1.function();
+        self.label_formatter(&fun)
+    }
+
     /// append a string to the default label
     pub fn label_post(&mut self, s: &str) -> &mut Self {
-        self.label_formatter(
-            &format!("function(v,a) {{ return {} + {:?}; }}",TICK_FORMAT,s)
-        )
+        self.tick_format(|t| t.post = s.into())
     }
 
     /// prepend a string to the default label
     pub fn label_pre(&mut self, s: &str) -> &mut Self {
-        self.label_formatter(
-            &format!("function(v,a) {{ return {:?} + {}; }}",s,TICK_FORMAT)
-        )
+        self.tick_format(|t| t.pre = s.into())
+    }
+
+    /// subtract a value from the displayed tick labels.
+    /// Unlike `transform`, the spacing of the axis is not changed.
+    pub fn offset(&mut self, subtract: f64) -> &mut Self {
+        self.tick_format(|t| t.offset = subtract)
+    }
+
+    /// multiply the displayed tick labels by a factor (after any offset).
+    pub fn scale(&mut self, factor: f64) -> &mut Self {
+        self.tick_format(|t| t.scale = factor)
     }
 
     /// force minimum value on axis
     pub fn min(&mut self, min: f64) -> &mut Self {
+        self.check_log_min(min);
         self.set_option("min",min.into());
         self
     }
 
+    // logarithms of zero or negative values are not defined
+    fn check_log_min(&self, min: f64) {
+        let key = (self.which,self.idx);
+        if min <= 0.0 && self.plot.log_axes.iter().any(|l| l.0 == key) {
+            panic!("log axis cannot have a minimum of {}",min);
+        }
+    }
+
     /// force maximum value on axis
     pub fn max(&mut self, max: f64) -> &mut Self {
         self.set_option("max",max.into());
         self
     }
 
+    /// show or hide the grid lines coming from this axis' ticks.
+    pub fn grid_lines(&mut self, show: bool) -> &mut Self {
+        let len = if show {JsonValue::Null} else {0.into()};
+        self.set_option("tickLength",len)
+    }
+
     /// set the position of an axis
     pub fn position(&mut self, side: Side) -> &mut Self {
         let pos = side.to_str();
-        // TBD also multiple x!
-        if pos == "right" {
+        // a second axis on the far side lines its ticks up with the first
+        if pos == "right" || pos == "top" {
             self.set_option("alignTicksWithAxis",1.into());
         }
         self.set_option("position",pos.into())
@@ -430,6 +1450,129 @@ impl <'a> Axis<'a> {
         self.set_option("mode","time".into())
     }
 
+    /// make this a logarithmic axis with the given base.
+    /// Ticks are placed at each power of the base over the range of the
+    /// axis, which is `min`/`max` if set, otherwise the range of the positive
+    /// data rounded out to powers of the base. Panics if the base is not
+    /// greater than one, or if the axis minimum is zero or negative.
+    pub fn log(&mut self, base: f64) -> &mut Self {
+        if ! (base.is_finite() && base > 1.0) {
+            panic!("log axis cannot have a base of {}",base);
+        }
+        let key = (self.which,self.idx);
+        self.plot.log_axes.retain(|l| l.0 != key);
+        self.plot.log_axes.push((key,LogAxis{base, minor: false}));
+        if let Some(min) = self.plot.options[self.which][self.idx]["min"].as_f64() {
+            self.check_log_min(min);
+        }
+        self.axis_function("transform",
+            &format!("function (v) {{ return Math.log(v)/Math.log({}); }}",base));
+        self.axis_function("inverseTransform",
+            &format!("function (v) {{ return Math.pow({},v); }}",base))
+    }
+
+    /// show unlabelled minor ticks between the powers of a log axis
+    /// (log axes only)
+    pub fn minor_ticks(&mut self, show: bool) -> &mut Self {
+        let key = (self.which,self.idx);
+        match self.plot.log_axes.iter_mut().find(|l| l.0 == key) {
+            Some(l) => l.1.minor = show,
+            None => panic!("minor_ticks() only applies to log axes")
+        }
+        self
+    }
+
+    /// round the ends of the axis out to round numbers, which are
+    /// multiples of 1, 2 or 5 times a power of ten, based on the range
+    /// of the data. An explicit `min` or `max` is kept.
+    pub fn nice_bounds(&mut self) -> &mut Self {
+        let key = (self.which,self.idx);
+        if ! self.plot.nice_axes.contains(&key) {
+            self.plot.nice_axes.push(key);
+        }
+        self
+    }
+
+    /// ask for about `n` ticks, leaving Flot to choose round values.
+    pub fn ticks_count(&mut self, n: u32) -> &mut Self {
+        self.set_option("ticks",n.into())
+    }
+
+    /// show times with a 12 hour clock, like "3:00 pm" (time axes).
+    pub fn twelve_hour(&mut self) -> &mut Self {
+        self.time().set_option("twelveHourClock",true.into())
+    }
+
+    /// names for the months, starting with January (time axes).
+    pub fn month_names(&mut self, names: &[&str]) -> &mut Self {
+        self.time().set_option("monthNames",names.to_vec().into())
+    }
+
+    /// names for the days of the week, starting with Sunday (time axes).
+    pub fn day_names(&mut self, names: &[&str]) -> &mut Self {
+        self.time().set_option("dayNames",names.to_vec().into())
+    }
+
+    /// always put labelled ticks at the ends of the axis, as well as
+    /// the usual ticks in between. Interior ticks closer than half a
+    /// tick spacing to an end are dropped, so labels do not overlap.
+    pub fn boundary_ticks(&mut self) -> &mut Self {
+        self.axis_function("ticks","function (axis) {
+    var ticks = axis.tickGenerator(axis);
+    var step = ticks.length > 1 ? ticks[1] - ticks[0] : 0;
+    ticks = $.grep(ticks, function (v) {
+        return v > axis.min + step/2 && v < axis.max - step/2;
+    });
+    return [axis.min].concat(ticks, [axis.max]);
+}")
+    }
+
+    /// rotate the tick labels by `degrees`, clockwise, e.g. -45 for crowded
+    /// category labels. Flot 0.8 cannot rotate labels, so this is done with
+    /// CSS after drawing; Flot does not leave extra room for the rotated
+    /// labels, which `Grid::min_border_margin` can provide.
+    pub fn rotate_ticks(&mut self, degrees: i32) -> &mut Self {
+        let class = format!("{}{}Axis",&self.which[..1],self.idx + 1);
+        self.plot.rotated_ticks.retain(|r| r.0 != class);
+        self.plot.rotated_ticks.push((class,degrees));
+        self
+    }
+
+    /// a title for the axis, like "Time (s)", shown beside it.
+    /// Uses the axislabels plugin, which is not distributed with Flot.
+    pub fn label(&mut self, text: &str) -> &mut Self {
+        self.plot.axis_labels = true;
+        self.set_option("axisLabel",escape_html(text).into())
+            .set_option("axisLabelUseCanvas",false.into())
+            .set_option("axisLabelPadding",6.into())
+    }
+
+    /// only label every `n`th tick, starting with the first, while still
+    /// drawing all the tick marks. Ticks already given with `tick_values`
+    /// or `tick_values_and_labels` are thinned out, so call this afterwards.
+    pub fn label_every(&mut self, n: u32) -> &mut Self {
+        let n = n.max(1) as usize;
+        let ticks = self.plot.options[self.which][self.idx]["ticks"].clone();
+        if ticks.is_array() {
+            let mut arr = JsonValue::new_array();
+            for (i,t) in ticks.members().enumerate() {
+                let (v,label) = if t.is_array() {
+                    (t[0].clone(),t[1].as_str().unwrap_or("").to_string())
+                } else {
+                    (t.clone(),t.to_string())
+                };
+                arr.push(array![v,if i % n == 0 {label} else {String::new()}]).unwrap();
+            }
+            self.set_option("ticks",arr)
+        } else {
+            self.axis_function("ticks",&format!("function (axis) {{
+    return $.map(axis.tickGenerator(axis), function (v, i) {{
+        return [[v, i % {} == 0 ? axis.tickFormatter(v, axis) : \"\"]];
+    }});
+}}",n))
+        }
+    }
+
     /// explicitly provide tick values.
     pub fn tick_values(&mut self, vv: &[f64]) -> &mut Self {
         let mut arr = JsonValue::new_array();
@@ -448,6 +1591,25 @@ impl <'a> Axis<'a> {
         self.set_option("ticks",arr)
     }
 
+    /// provide tick values, with labels made by a Rust function.
+    /// Since the labels are worked out in advance, the ticks are fixed
+    /// and will not be relabelled if the plot is zoomed or panned.
+    pub fn tick_formatter_rust<F>(&mut self, ticks: &[f64], f: F) -> &mut Self
+    where F: Fn(f64)->String {
+        let labels: Vec<_> = ticks.iter().map(|&v| f(v)).collect();
+        let pairs: Vec<_> = ticks.iter().zip(&labels).map(|(&v,l)| (v,l.as_str())).collect();
+        self.tick_values_and_labels(&pairs)
+    }
+
+}
+
+// things drawn on top of the plot at data coordinates
+#[derive(Clone)]
+enum Annotation {
+    // HTML text
+    Text(f64,f64,String),
+    // small circle with this colour
+    Marker(f64,f64,String),
 }
 
 /// represents 'markings' or plot annotations.
@@ -457,8 +1619,10 @@ pub struct Markings<'a> {
 
 impl <'a> Markings<'a> {
     fn new(plot: &'a mut Plot) -> Markings<'a> {
-        plot.set_option("grid","markings",array![]);
-        Markings{plot: plot}
+        if plot.options["grid"]["markings"].is_null() {
+            plot.set_option("grid","markings",array![]);
+        }
+        Markings{plot}
     }
 
     fn markings(&mut self) -> &mut JsonValue {
@@ -498,10 +1662,16 @@ impl <'a> Markings<'a> {
         })
     }
 
+    /// mark the point (x,y) with a small circle
+    pub fn point(&mut self, x: f64, y: f64, color: &str) -> &mut Self {
+        self.plot.annotations.push(Annotation::Marker(x,y,color.into()));
+        self
+    }
+
     /// set the color of the last marking defined
     pub fn color(&mut self, color: &str) -> &mut Self {
         {
-            let mut arr = self.markings();
+            let arr = self.markings();
             let len = arr.len();
             arr[len-1]["color"] = color.into();
         }
@@ -520,7 +1690,7 @@ impl <'a> Grid<'a> {
         if plot.options["grid"].is_null() {
             plot.options["grid"] = object!{};
         }
-        Grid{plot: plot}
+        Grid{plot}
     }
 
     /// set any grid option not covered by this API
@@ -530,6 +1700,11 @@ impl <'a> Grid<'a> {
         self
     }
 
+    /// respond to clicks on data points, with the "plotclick" event.
+    pub fn clickable(&mut self) -> &mut Self {
+        self.set_option("clickable",true.into())
+    }
+
     /// hide the grid completely
     pub fn hide(&mut self) -> &mut Self {
         self.set_option("show",false.into())
@@ -545,6 +1720,20 @@ impl <'a> Grid<'a> {
         self.set_option("backgroundColor",back.into())
     }
 
+    /// minimum space in pixels kept around the plot area, so that
+    /// tick labels at the edges are not clipped
+    pub fn min_border_margin(&mut self, px: u32) -> &mut Self {
+        self.set_option("minBorderMargin",px.into())
+    }
+
+    /// draw a pattern behind the plot. This is a CSS background, so it is
+    /// only visible through a transparent or translucent background colour
+    /// like "rgba(255,255,255,0.5)"
+    pub fn background_pattern(&mut self, pattern: BackgroundPattern) -> &mut Self {
+        self.plot.pattern = Some(pattern);
+        self
+    }
+
     /// background gradient, from bottom colour to top colour
     pub fn background_gradient(&mut self, bottom: &str, top: &str) -> &mut Self {
         self.set_option("backgroundColor",object!{"colors" => array![bottom,top]})
@@ -562,7 +1751,7 @@ impl <'a> Legend<'a> {
         if plot.options["legend"].is_null() {
             plot.options["legend"] = object!{};
         }
-        Legend{plot: plot}
+        Legend{plot}
     }
 
     /// any legend option not covered by this API
@@ -580,12 +1769,30 @@ impl <'a> Legend<'a> {
         }
     }
 
+    /// draw the legend inside another element, given as a jQuery selector
+    /// like "#legend".
+    pub fn container(&mut self, selector: &str) -> &mut Self {
+        self.plot.option_functions.push(format!("legend.container = $({:?})",selector));
+        self
+    }
+
+    /// lay out the legend entries in `n` columns.
+    pub fn columns(&mut self, n: u32) -> &mut Self {
+        self.set_option("noColumns",n.into())
+    }
+
+    /// opacity of the legend background, from 0.0 to 1.0.
+    pub fn background_opacity(&mut self, opacity: f32) -> &mut Self {
+        self.set_option("backgroundOpacity",opacity.into())
+    }
+
 }
 
 
 /// represents a particular plot
 pub struct Plot {
     series: Arena<Series>,
+    finished: Vec<Series>,
     placeholder: String,
     options: JsonValue,
     time: bool,
@@ -593,36 +1800,89 @@ pub struct Plot {
     bounds: (u32,u32),
     title: String,
     option_functions: Vec<String>,
+    tick_formats: Vec<((&'static str,usize),TickFormat)>,
+    log_axes: Vec<((&'static str,usize),LogAxis)>,
+    nice_axes: Vec<(&'static str,usize)>,
     description: Vec<String>,
+    annotations: Vec<Annotation>,
+    notes: usize,
+    toggle_legend: bool,
+    compact: bool,
+    base64: bool,
+    legend_side: Option<Side>,
+    pattern: Option<BackgroundPattern>,
+    watermark: Option<(String,f32)>,
+    rotated_ticks: Vec<(String,i32)>,
+    precision: Option<usize>,
+    fluid: bool,
+    click_handlers: Vec<String>,
+    shared_tooltip: bool,
+    tooltip: Option<String>,
+    stack: bool,
+    errorbars: bool,
+    dashes: bool,
+    axis_labels: bool,
+    bubbles: bool,
+    percent: bool,
+    fillbetween: bool,
+    linked: Vec<String>,
+    stats_table: bool,
+    aspect: Option<f64>,
 }
 
 impl Plot {
     fn new(name: &str, title: &str, bounds: (u32,u32)) -> Plot {
         Plot {
             series: Arena::new(),
+            finished: Vec::new(),
             placeholder: name.into(),
             options: object!{},
             time: false,
             symbols: false,
-            bounds: bounds,
-            title: title.into(),
+            bounds,
+            title: escape_html(title),
             option_functions: Vec::new(),
+            tick_formats: Vec::new(),
+            log_axes: Vec::new(),
+            nice_axes: Vec::new(),
             description: Vec::new(),
+            annotations: Vec::new(),
+            notes: 0,
+            toggle_legend: false,
+            compact: false,
+            base64: false,
+            legend_side: None,
+            pattern: None,
+            watermark: None,
+            rotated_ticks: Vec::new(),
+            precision: None,
+            fluid: false,
+            click_handlers: Vec::new(),
+            shared_tooltip: false,
+            tooltip: None,
+            stack: false,
+            errorbars: false,
+            dashes: false,
+            axis_labels: false,
+            bubbles: false,
+            percent: false,
+            fillbetween: false,
+            linked: Vec::new(),
+            stats_table: false,
+            aspect: None,
         }
     }
 
+    /// add a table below the plot giving the minimum, maximum, mean and
+    /// last y value of each labelled series.
+    pub fn stats_table(&mut self) -> &mut Self {
+        self.stats_table = true;
+        self
+    }
+
     /// add a paragrath of text below a plot.
     pub fn text(&mut self, txt: &str) -> &mut Self {
-        let mut escaped = String::new();
-        for ch in txt.chars() {
-            match ch {
-            '<' => escaped.push_str("&lt;"),
-            '>' => escaped.push_str("&gt;"),
-            '&' => escaped.push_str("&amp;"),
-            _ => escaped.push(ch)
-            }
-        }
-        self.description.push(escaped);
+        self.description.push(escape_html(txt));
         self
     }
 
@@ -633,9 +1893,50 @@ impl Plot {
     }
 
 
-    /// the size in pixels (width,height) of the plot area
+    /// set the title of the plot as HTML, which is not escaped.
+    pub fn title_html(&mut self, html: &str) -> &mut Self {
+        self.title = html.into();
+        self
+    }
+
+    /// show large faint text like "DRAFT" behind the plot.
+    /// The text will be HTML escaped.
+    pub fn watermark(&mut self, text: &str, opacity: f32) -> &mut Self {
+        self.watermark = Some((escape_html(text),opacity));
+        self
+    }
+
+    /// place a text annotation at the data point (x,y).
+    /// The text will be HTML escaped.
+    pub fn annotate(&mut self, x: f64, y: f64, txt: &str) -> &mut Self {
+        self.annotations.push(Annotation::Text(x,y,escape_html(txt)));
+        self
+    }
+
+    /// add a numbered footnote marker at (x,y), with the
+    /// text listed below the plot. Returns the note number.
+    pub fn note(&mut self, x: f64, y: f64, txt: &str) -> usize {
+        self.notes += 1;
+        let n = self.notes;
+        self.annotate(x,y,&format!("[{}]",n));
+        self.description.push(format!("<sup>[{}]</sup> {}",n,escape_html(txt)));
+        n
+    }
+
+    /// the size in pixels (width,height) of the plot area.
+    /// If an aspect ratio has been set, the height is calculated from the width.
     pub fn size(&mut self,width:u32,height:u32) -> &mut Self {
         self.bounds = (width,height);
+        if let Some(ratio) = self.aspect {
+            self.aspect_ratio(ratio);
+        }
+        self
+    }
+
+    /// set the height from the width, so that width/height is `ratio`.
+    pub fn aspect_ratio(&mut self, ratio: f64) -> &mut Self {
+        self.aspect = Some(ratio);
+        self.bounds.1 = (self.bounds.0 as f64/ratio).round() as u32;
         self
     }
 
@@ -654,6 +1955,24 @@ impl Plot {
         Axis::new("yaxes",self,2)
     }
 
+    /// the second x axis object. Use `Series::xaxis(2)` to put
+    /// a series on it, and `position(Side::Top)` to show it above.
+    pub fn xaxis2<'a>(&'a mut self) -> Axis<'a> {
+        Axis::new("xaxes",self,2)
+    }
+
+    /// the nth x axis object, counting from 1.
+    /// Any missing axes before it are also created.
+    pub fn xaxis_n<'a>(&'a mut self, n: u32) -> Axis<'a> {
+        Axis::new("xaxes",self,n as usize)
+    }
+
+    /// the nth y axis object, counting from 1.
+    /// Use `Series::yaxis(n)` to plot a series against it.
+    pub fn yaxis_n<'a>(&'a mut self, n: u32) -> Axis<'a> {
+        Axis::new("yaxes",self,n as usize)
+    }
+
     /// create a data series with individual points.
     /// The data is anything that converts to an iterator
     /// of `(f64,f64)` tuples. If label is the empty string,
@@ -669,28 +1988,346 @@ impl Plot {
         self.series.alloc(Series::new(PlotKind::Lines,label,data))
     }
 
+    /// create a bubble chart, where each item is `(x,y,radius)` with
+    /// the radius in pixels. Bubbles are partly transparent, so
+    /// overlapping ones remain visible.
+    pub fn bubbles<T>(&self, label: &str, data: T) -> &mut Series
+    where T: IntoIterator<Item=(f64,f64,f64)> {
+        let series = self.points(label,Vec::new());
+        for (x,y,r) in data {
+            series.data["data"].push(array![x,y,r]).unwrap();
+        }
+        series.data["bubbles"] = true.into();
+        series.invisible_points();
+        series
+    }
+
+    /// create a data series with points and vertical error bars.
+    /// Each item is `(x,y,error)`, and the bar goes from `y-error` to `y+error`.
+    /// Uses the errorbars plugin.
+    pub fn points_with_errors<T>(&self, label: &str, data: T) -> &mut Series
+    where T: IntoIterator<Item=(f64,f64,f64)> {
+        self.errorbar_series("y",label,data)
+    }
+
+    /// create a data series with points and horizontal error bars.
+    /// Each item is `(x,y,error)`, and the bar goes from `x-error` to `x+error`.
+    pub fn points_with_x_errors<T>(&self, label: &str, data: T) -> &mut Series
+    where T: IntoIterator<Item=(f64,f64,f64)> {
+        self.errorbar_series("x",label,data)
+    }
+
+    fn errorbar_series<T>(&self, axis: &str, label: &str, data: T) -> &mut Series
+    where T: IntoIterator<Item=(f64,f64,f64)> {
+        let series = self.points(label,Vec::new());
+        for (x,y,e) in data {
+            series.data["data"].push(array![x,y,e]).unwrap();
+        }
+        let points = series.kind_ref();
+        points["errorbars"] = axis.into();
+        points[format!("{}err",axis)] = object!{
+            "show" => true,
+            "upperCap" => "-",
+            "lowerCap" => "-"
+        };
+        series
+    }
+
+    /// create a data series with lines from y values,
+    /// which are plotted against their index 0,1,2...
+    pub fn lines_y<T>(&self, label: &str, ys: T) -> &mut Series
+    where T: IntoIterator<Item=f64> {
+        self.lines(label,indexed(ys))
+    }
+
+    /// create a data series with points from y values,
+    /// which are plotted against their index 0,1,2...
+    pub fn points_y<T>(&self, label: &str, ys: T) -> &mut Series
+    where T: IntoIterator<Item=f64> {
+        self.points(label,indexed(ys))
+    }
+
+    /// create a data series with bars from y values,
+    /// which are plotted against their index 0,1,2...
+    pub fn bars_y<T>(&self, label: &str, ys: T) -> &mut Series
+    where T: IntoIterator<Item=f64> {
+        self.bars(label,indexed(ys))
+    }
+
+    /// create a data series with lines, where `None` leaves a gap
+    /// so the line is broken rather than joining across missing data.
+    pub fn lines_opt<T>(&self, label: &str, data: T) -> &mut Series
+    where T: IntoIterator<Item=Option<(f64,f64)>> {
+        let series = self.lines(label,Vec::new());
+        series.set_points(data.into_iter().collect());
+        series
+    }
+
     /// create a data series with bars (histogram).
     pub fn bars<T>(&self, label: &str, data: T) -> &mut Series
     where T: IntoIterator<Item=(f64,f64)> {
         self.series.alloc(Series::new(PlotKind::Bars,label,data))
     }
 
+    /// lines for two quantities with different scales: `left` uses the
+    /// first y axis and `right` a second y axis on the right. Each axis
+    /// is coloured like its series.
+    pub fn dual_axis<T1,T2>(&mut self, left: (&str,T1), right: (&str,T2)) -> (&mut Series,&mut Series)
+    where T1: IntoIterator<Item=(f64,f64)>, T2: IntoIterator<Item=(f64,f64)> {
+        let (c1,c2) = (colors::CATEGORY10[0],colors::CATEGORY10[1]);
+        self.yaxis().position(Side::Left).set_option("color",c1.into());
+        self.yaxis2().position(Side::Right).set_option("color",c2.into());
+        let l = self.series.alloc(Series::new(PlotKind::Lines,left.0,left.1));
+        l.color(c1);
+        let r = self.series.alloc(Series::new(PlotKind::Lines,right.0,right.1));
+        r.color(c2).yaxis(2);
+        (l,r)
+    }
+
+    /// a filled band between the `lower` and `upper` lines, such as a
+    /// confidence interval. The lines themselves are not drawn; the
+    /// returned series can be given a colour and fill opacity.
+    pub fn band<T1,T2>(&self, label: &str, lower: T1, upper: T2) -> &mut Series
+    where T1: IntoIterator<Item=(f64,f64)>, T2: IntoIterator<Item=(f64,f64)> {
+        let series = self.lines(label,upper);
+        series.band = Some(lower.into_iter().collect());
+        series.line_width(0).fill(0.4);
+        series
+    }
+
+    /// create bars showing how the values are distributed,
+    /// counted into `bins` equal bins. The bars touch.
+    pub fn histogram<T>(&self, label: &str, values: T, bins: usize) -> &mut Series
+    where T: IntoIterator<Item=f64> {
+        let (counts,width) = histogram(values,bins);
+        let series = self.bars(label,counts);
+        series.width(width);
+        series
+    }
+
+    /// plot a function as lines, sampled at `n` evenly spaced points
+    /// from `x1` to `x2` inclusive. Increase `n` for sharply curving functions.
+    pub fn function<F>(&self, label: &str, f: F, x1: f64, x2: f64, n: usize) -> &mut Series
+    where F: Fn(f64)->f64 {
+        self.lines(label,range_n(x1,x2,n).map(|x| (x,f(x))))
+    }
+
+    /// create a candlestick series from `(time,open,high,low,close)` data.
+    /// The candles are drawn as markings: rising candles are green and
+    /// falling candles red, unless changed with `Series::candle_colors`.
+    /// The series itself is not drawn, but keeps the axes scaled to the data
+    /// and provides a legend entry.
+    pub fn candlestick<T>(&self, label: &str, data: T) -> &mut Series
+    where T: IntoIterator<Item=(f64,f64,f64,f64,f64)> {
+        let data: Vec<_> = data.into_iter().collect();
+        let s = self.series.alloc(Series::new(PlotKind::Lines,label,
+            data.iter().flat_map(|c| vec![(c.0,c.2),(c.0,c.3)])
+        ));
+        s.kind_ref()["show"] = false.into();
+        s.candles = Some(Candles {data, up: "#2ca02c".into(), down: "#d62728".into()});
+        s
+    }
+
+    // check that data is not empty and only has finite values
+    fn checked<T>(&self, kind: PlotKind, label: &str, data: T) -> Result<&mut Series,DataError>
+    where T: IntoIterator<Item=(f64,f64)> {
+        let data: Vec<_> = data.into_iter().collect();
+        if data.is_empty() {
+            return Err(DataError::Empty(label.into()));
+        }
+        if let Some(i) = data.iter().position(|p| ! (p.0.is_finite() && p.1.is_finite())) {
+            return Err(DataError::NonFinite(label.into(),i,data[i]));
+        }
+        Ok(self.series.alloc(Series::new(kind,label,data)))
+    }
+
+    /// like `points`, but fails if the data is empty or not finite.
+    pub fn try_points<T>(&self, label: &str, data: T) -> Result<&mut Series,DataError>
+    where T: IntoIterator<Item=(f64,f64)> {
+        self.checked(PlotKind::Points,label,data)
+    }
+
+    /// like `lines`, but fails if the data is empty or not finite.
+    pub fn try_lines<T>(&self, label: &str, data: T) -> Result<&mut Series,DataError>
+    where T: IntoIterator<Item=(f64,f64)> {
+        self.checked(PlotKind::Lines,label,data)
+    }
+
+    /// like `bars`, but fails if the data is empty or not finite.
+    pub fn try_bars<T>(&self, label: &str, data: T) -> Result<&mut Series,DataError>
+    where T: IntoIterator<Item=(f64,f64)> {
+        self.checked(PlotKind::Bars,label,data)
+    }
+
+    /// plot a function as lines from `x1` to `x2`, using more points where
+    /// it curves sharply. An interval is split in two while its midpoint
+    /// is further than `tolerance` (in y units) from the straight line
+    /// joining its ends.
+    pub fn function_adaptive<F>(&self, label: &str, f: F, x1: f64, x2: f64, tolerance: f64) -> &mut Series
+    where F: Fn(f64)->f64 {
+        let mut points = Vec::new();
+        let xs: Vec<_> = range_n(x1,x2,17).collect();
+        points.push((x1,f(x1)));
+        for w in xs.windows(2) {
+            subdivide(&f,(w[0],f(w[0])),(w[1],f(w[1])),tolerance,12,&mut points);
+        }
+        self.lines(label,points)
+    }
+
     /// position of legend (Corner::None to hide)
     pub fn legend_pos(&mut self, pos: Corner) -> &mut Self {
         self.legend().pos(pos);
         self
     }
 
+    /// put the legend outside the plot area, on the given side.
+    pub fn legend_outside(&mut self, side: Side) -> &mut Self {
+        self.legend_side = Some(side);
+        self
+    }
+
     /// legend object
     pub fn legend<'a>(&'a mut self) -> Legend<'a> {
         Legend::new(self)
     }
 
+    /// run some JavaScript when a data point is clicked.
+    /// The code can use `x` and `y` (the point), `label` (the series label),
+    /// `index` (the index of the point in the series) and Flot's `item`
+    /// and `pos` objects. Makes the grid clickable.
+    pub fn on_click(&mut self, js_body: &str) -> &mut Self {
+        self.grid().clickable();
+        self.click_handlers.push(js_body.into());
+        self
+    }
+
+    /// show a tooltip with the series label and the point's value
+    /// when the mouse is over a point.
+    pub fn tooltip(&mut self) -> &mut Self {
+        self.tooltip_format("label + \" (\" + x + \", \" + y + \")\"")
+    }
+
+    /// show a tooltip when the mouse is over a point, with HTML made by a
+    /// Javascript expression in `label`, `x` and `y`, or the full function.
+    pub fn tooltip_format(&mut self, expr_or_fun: &str) -> &mut Self {
+        self.grid().set_option("hoverable",true.into());
+        self.tooltip = Some(if expr_or_fun.starts_with("function") {
+            expr_or_fun.into()
+        } else {
+            format!("function (label, x, y) {{ return {}; }}",expr_or_fun)
+        });
+        self
+    }
+
+    /// when the mouse is over the plot, show one tooltip listing the
+    /// value of every labelled series at the nearest x position.
+    pub fn shared_tooltip(&mut self) -> &mut Self {
+        self.grid().set_option("hoverable",true.into());
+        self.shared_tooltip = true;
+        self
+    }
+
+    /// write series data as flat arrays of x and y values, which are
+    /// zipped back into points by the page. This makes the output smaller
+    /// for series with very many points.
+    pub fn compact_data(&mut self) -> &mut Self {
+        self.compact = true;
+        self
+    }
+
+    /// clicking on a legend entry hides or shows its series.
+    pub fn toggleable_legend(&mut self) -> &mut Self {
+        self.toggle_legend = true;
+        self
+    }
+
     /// grid object
     pub fn grid<'a>(&'a mut self) -> Grid<'a> {
         Grid::new(self)
     }
 
+    /// set defaults for all series in this plot.
+    pub fn series_defaults(&mut self, defaults: SeriesDefaults) -> &mut Self {
+        {
+            let series = &mut self.options["series"];
+            if let Some(w) = defaults.line_width {
+                series["lines"]["lineWidth"] = w.into();
+            }
+            if let Some(r) = defaults.point_radius {
+                series["points"]["radius"] = r.into();
+            }
+            if let Some(w) = defaults.bar_width {
+                series["bars"]["barWidth"] = w.into();
+            }
+            if let Some(s) = defaults.shadow_size {
+                series["shadowSize"] = s.into();
+            }
+            if let Some(f) = defaults.fill {
+                series["lines"]["fill"] = f.into();
+                series["bars"]["fill"] = f.into();
+            }
+        }
+        self
+    }
+
+    /// set the id of the plot's div, instead of "plot1", "plot2", etc.
+    /// Ids may contain letters, digits, '-' and '_', and must be unique
+    /// within the page, which is checked when rendering.
+    pub fn id(&mut self, id: &str) -> &mut Self {
+        check_id(id);
+        self.placeholder = id.into();
+        self
+    }
+
+    /// round the data of all series to `decimals` decimal places when
+    /// rendering, unless they have their own `Series::precision`.
+    pub fn precision(&mut self, decimals: usize) -> &mut Self {
+        self.precision = Some(decimals);
+        self
+    }
+
+    /// light text and grid lines on a dark background, with a slightly
+    /// lighter legend and the `colors::PASTEL` palette.
+    pub fn dark_theme(&mut self) -> &mut Self {
+        self.grid().color("#ddd").background_color("#222")
+            .set_option("tickColor","rgba(255,255,255,0.15)".into());
+        self.legend().set_option("backgroundColor","#444".into())
+            .background_opacity(0.85);
+        self.colors(colors::PASTEL)
+    }
+
+    /// the colours given to series without an explicit colour, in order,
+    /// such as a palette from the `colors` module like `colors::COLORBLIND`.
+    pub fn colors<S: AsRef<str>>(&mut self, colors: &[S]) -> &mut Self {
+        let colors: Vec<_> = colors.iter().map(|c| c.as_ref()).collect();
+        self.options["colors"] = colors.into();
+        self
+    }
+
+    /// switch off the default shadows for all series in this plot.
+    pub fn no_shadows(&mut self) -> &mut Self {
+        self.set_option("series","shadowSize",0.into())
+    }
+
+    /// stack all the series on top of each other, rather than overlapping.
+    /// Uses the stack plugin. Flot adds up values at the same positions,
+    /// so the series must share x values; this is checked when rendering.
+    /// Use `Series::stack` to only stack some series.
+    pub fn stack(&mut self) -> &mut Self {
+        self.stack = true;
+        self.set_option("series","stack",true.into())
+    }
+
+    /// stack the series so that each x position shows the percentage
+    /// contribution of each series. The series must share x values.
+    pub fn stacked_percent_bars(&mut self) -> &mut Self {
+        self.stack = true;
+        self.percent = true;
+        self.set_option("series","stack",true.into());
+        self.yaxis().min(0.0).max(100.0).label_post("%");
+        self
+    }
+
     pub fn extra_symbols(&mut self) -> &mut Self {
         self.symbols = true;
         self
@@ -701,6 +2338,45 @@ impl Plot {
         Markings::new(self)
     }
 
+    /// draw a heatmap of a grid of values, where `grid[row][col]` covers
+    /// x from `col` to `col+1` and y from `row` to `row+1`.
+    /// The axis ranges are set to the grid dimensions. NaN cells are left empty.
+    pub fn heatmap(&mut self, grid: &[Vec<f64>], color_scale: ColorScale) -> &mut Self {
+        let values = grid.iter().flat_map(|row| row.iter()).filter(|v| ! v.is_nan());
+        let (lo,hi) = values.fold((f64::INFINITY,f64::NEG_INFINITY),
+            |(lo,hi),&v| (lo.min(v),hi.max(v)));
+        let ncols = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+        {
+            let mut markings = self.markings();
+            for (i,row) in grid.iter().enumerate() {
+                for (j,&v) in row.iter().enumerate() {
+                    if v.is_nan() {
+                        continue;
+                    }
+                    let t = if hi > lo {(v - lo)/(hi - lo)} else {0.0};
+                    let (x,y) = (j as f64, i as f64);
+                    markings.area(x,x+1.0,y,y+1.0).color(&color_scale.color(t));
+                }
+            }
+        }
+        self.xaxis().min(0.0).max(ncols as f64);
+        self.yaxis().min(0.0).max(grid.len() as f64);
+        self
+    }
+
+    /// shade a vertical band between `x1` and `x2` with a colour.
+    /// Multiple spans accumulate
+    pub fn vspan(&mut self, x1: f64, x2: f64, color: &str) -> &mut Self {
+        self.markings().vertical_area(x1,x2).color(color);
+        self
+    }
+
+    /// shade a horizontal band between `y1` and `y2` with a colour.
+    pub fn hspan(&mut self, y1: f64, y2: f64, color: &str) -> &mut Self {
+        self.markings().horizontal_area(y1,y2).color(color);
+        self
+    }
+
     /// set any option field not exposed in this API.
     pub fn set_option(&mut self, key: &str, subkey: &str, val: JsonValue) -> &mut Self {
         if self.options[key].is_null() {
@@ -711,45 +2387,622 @@ impl Plot {
     }
 
     fn render_placeholder(&self, f: &mut Write) -> io::Result<()> {
+        // plots in a Page::columns grid fill their cell
+        let width = if self.fluid {"100%".to_string()} else {format!("{}px",self.bounds.0)};
         if ! self.title.is_empty() {
-            write!(f, "<h2 style='text-align: center;width:{}px'>{}</h2>\n"
-                ,self.bounds.0,self.title)?;
+            writeln!(f, "<h2 style='text-align: center;width:{}'>{}</h2>"
+                ,width,self.title)?;
+        }
+        for &(ref class,degrees) in &self.rotated_ticks {
+            // labels turn about the end nearest their tick
+            let (origin,shift,align) = if degrees < 0 {("100% 0","-50%","right")} else {("0 0","50%","left")};
+            writeln!(f, "<style>#{} .{} .tickLabel {{ text-align:{}; transform-origin:{}; \
+transform:translateX({}) rotate({}deg); }}</style>",self.placeholder,class,align,origin,shift,degrees)?;
+        }
+        let pattern = match self.pattern {
+            Some(ref p) => format!(";{}",p.to_css()),
+            None => String::new()
+        };
+        let mut div = format!("<div id={:?} style=\"width:{};height:{}px{}\"></div>\n",
+            self.placeholder,width,self.bounds.1,pattern);
+        if let Some((ref text,opacity)) = self.watermark {
+            // the watermark comes first, so the plot is drawn over it
+            div = format!("<div style=\"position:relative;width:{0};height:{1}px\">
+<div style=\"position:absolute;top:0;left:0;width:{0};height:{1}px;display:flex;align-items:center;\
+justify-content:center;font-size:{2}px;font-weight:bold;opacity:{3};transform:rotate(-20deg);\
+pointer-events:none\">{4}</div>
+{5}</div>\n",width,self.bounds.1,self.bounds.1/4,opacity,text,div);
+        }
+        if let Some(ref side) = self.legend_side {
+            let direction = match *side {
+                Side::Right => "row",
+                Side::Left => "row-reverse",
+                Side::Bottom => "column",
+                Side::Top => "column-reverse",
+            };
+            write!(f, "<div style=\"display:flex;flex-direction:{};align-items:flex-start\">\n{}",
+                direction,div)?;
+            write!(f, "<div id=\"{}_legend\" style=\"margin:10px\"></div>\n</div>\n",self.placeholder)?;
+        } else {
+            write!(f, "{}", div)?;
         }
-        write!(f, "<div id={:?} style=\"width:{}px;height:{}px\"></div>\n",
-            self.placeholder,self.bounds.0,self.bounds.1)?;
 
         for s in &self.description {
-            write!(f, "<p style='width:{}px;margin-left:2em;margin-right:2em'>{}</p>",self.bounds.0,s)?;
+            write!(f, "<p style='width:{};margin-left:2em;margin-right:2em'>{}</p>",width,s)?;
+        }
+        if self.stats_table {
+            self.render_stats_table(f)?;
         }
         Ok(())
     }
 
-    fn render_script(self, f: &mut Write) -> io::Result<()> {
-        let series = self.series.into_vec();
-        let mut data = '['.to_string();
+    fn render_stats_table(&self, f: &mut Write) -> io::Result<()> {
+        let round = |v: f64| (v*1e4).round()/1e4;
+        writeln!(f, "<table cellpadding='4' style='margin-left:2em;border-collapse:collapse;text-align:right'>")?;
+        writeln!(f, "<tr><th style='text-align:left'>Series</th><th>Min</th><th>Max</th><th>Mean</th><th>Last</th></tr>")?;
+        for s in &self.finished {
+            let label = match s.data["label"].as_str() {
+                Some(label) => label,
+                None => continue
+            };
+            let ys: Vec<_> = s.points().into_iter().filter_map(|p| p.map(|(_,y)| y)).collect();
+            if ys.is_empty() {
+                continue;
+            }
+            let min = ys.iter().cloned().fold(f64::INFINITY,f64::min);
+            let max = ys.iter().cloned().fold(f64::NEG_INFINITY,f64::max);
+            let mean = ys.iter().sum::<f64>()/ys.len() as f64;
+            writeln!(f, "<tr><td style='text-align:left'>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                label,round(min),round(max),round(mean),round(ys[ys.len()-1]))?;
+        }
+        writeln!(f, "</table>")
+    }
+
+    // scale the y values at each x position so that they sum to 100
+    fn normalize_percent(series: &mut [Series]) {
+        let mut totals: HashMap<u64,f64> = HashMap::new();
+        for s in series.iter() {
+            for p in s.data["data"].members() {
+                if let (Some(x),Some(y)) = (p[0].as_f64(),p[1].as_f64()) {
+                    *totals.entry(x_key(x)).or_insert(0.0) += y;
+                }
+            }
+        }
+        for s in series.iter_mut() {
+            for p in s.data["data"].members_mut() {
+                if let (Some(x),Some(y)) = (p[0].as_f64(),p[1].as_f64()) {
+                    let total = totals.get(&x_key(x)).cloned().unwrap_or(0.0);
+                    if total != 0.0 {
+                        p[1] = (100.0*y/total).into();
+                    }
+                }
+            }
+        }
+    }
+
+    // called before rendering: the series are moved out of the arena
+    // so they can be inspected and adjusted
+    fn finish(&mut self) {
+        let arena = mem::replace(&mut self.series, Arena::new());
+        let mut added = arena.into_vec();
+        let mut baselines = Vec::new();
+        for s in added.iter_mut() {
+            let lower = match (s.fill_to,s.band.take()) {
+                (Some(y),_) => Some(s.points().into_iter()
+                    .filter_map(|p| p.map(|(x,_)| (x,y))).collect()),
+                (None,lower) => lower
+            };
+            if let Some(points) = lower {
+                let id = format!("{}_base_{}",self.placeholder,self.finished.len() + baselines.len() + 1);
+                let mut base = Series::new(PlotKind::Lines,"",points);
+                base.data["id"] = id.as_str().into();
+                base.kind_ref()["lineWidth"] = 0.into();
+                // an explicit colour does not use up one of the default colours
+                base.data["color"] = "rgba(0,0,0,0)".into();
+                base.data["shadowSize"] = 0.into();
+                s.data["fillBetween"] = id.into();
+                baselines.push(base);
+            }
+        }
+        if ! baselines.is_empty() {
+            self.fillbetween = true;
+        }
+        // filled steps are drawn more reliably if the staircase
+        // is built into the data
+        for s in added.iter_mut() {
+            let lines = &s.data["lines"];
+            let filled = ! (lines["fill"].is_null() || lines["fill"] == false || lines["fill"] == 0);
+            if lines["steps"] == true && filled {
+                s.steps_direction(StepDirection::After);
+                s.kind_ref()["steps"] = false.into();
+            }
+        }
+        // dashed lines are drawn by the dashes plugin. Flot still draws
+        // them with no width, so it fills them and finds them for hover
+        let default_width = self.options["series"]["lines"]["lineWidth"].clone();
+        for s in added.iter_mut() {
+            if ! s.data["dashes"].is_null() {
+                let width = mem::replace(&mut s.data["lines"]["lineWidth"],0.into());
+                let width = if width.is_null() {default_width.clone()} else {width};
+                if ! width.is_null() {
+                    s.data["dashes"]["lineWidth"] = width;
+                }
+            }
+        }
+        let mut candles = Vec::new();
+        for s in added.iter_mut() {
+            if let Some(c) = s.candles.take() {
+                candles.push(c);
+            }
+        }
+        for c in candles {
+            // candle bodies are 60% of the smallest gap between times
+            let gap = c.data.windows(2).map(|w| (w[1].0 - w[0].0).abs())
+                .filter(|&d| d > 0.0).fold(f64::INFINITY,f64::min);
+            let half = if gap.is_finite() {0.3*gap} else {0.5};
+            let mut markings = self.markings();
+            for &(t,open,high,low,close) in &c.data {
+                let color = if close >= open {&c.up} else {&c.down};
+                markings.add_marking(object!{
+                    "xaxis" => object!{"from"=>t,"to"=>t},
+                    "yaxis" => object!{"from"=>low,"to"=>high},
+                    "color" => color.as_str()
+                });
+                markings.area(t-half,t+half,open.min(close),open.max(close)).color(color);
+            }
+        }
+        added.extend(baselines);
+        self.finished.extend(added);
+        if self.finished.iter().any(|s| ! s.data["stack"].is_null()) {
+            self.stack = true;
+        }
+        if self.finished.iter().any(|s| ! s.data["points"]["errorbars"].is_null()) {
+            self.errorbars = true;
+        }
+        if self.finished.iter().any(|s| ! s.data["dashes"].is_null()) {
+            self.dashes = true;
+        }
+        // these points are drawn by our own hook
+        if self.finished.iter().any(|s| s.data["bubbles"] == true || ! s.data["pointColors"].is_null()) {
+            self.bubbles = true;
+        }
+        // symbols other than circles need the symbol plugin
+        if self.finished.iter().any(|s| s.symbols) {
+            self.symbols = true;
+        }
+        if self.percent {
+            Self::normalize_percent(&mut self.finished);
+        }
+        // make sure that every axis referenced by a series exists
+        let mut referenced = Vec::new();
+        for s in &self.finished {
+            for &(name,which) in &[("xaxis","xaxes"),("yaxis","yaxes")] {
+                if let Some(n) = s.data[name].as_f64() {
+                    referenced.push((which,n as usize));
+                }
+            }
+        }
+        for (which,n) in referenced {
+            if n > 1 && self.options[which].len() < n {
+                Axis::new(which,self,n);
+            }
+        }
+        // log axes are given whole powers of the base as bounds, since
+        // Flot's autoscaling margin can take the minimum down to zero
+        let bases: Vec<_> = self.log_axes.iter().map(|&(key,ref log)| (key,log.base)).collect();
+        for (key,base) in bases {
+            if let Some((lo,hi)) = self.axis_range(key,true) {
+                let k1 = lo.log(base).floor();
+                let k2 = hi.log(base).ceil().max(k1 + 1.0);
+                let axis = &mut self.options[key.0][key.1];
+                if axis["min"].is_null() {
+                    axis["min"] = base.powf(k1).into();
+                }
+                if axis["max"].is_null() {
+                    axis["max"] = base.powf(k2).into();
+                }
+            }
+        }
+        for key in self.nice_axes.clone() {
+            if let Some((lo,hi)) = self.axis_range(key,false) {
+                if lo < hi {
+                    let step = nice_step((hi - lo)/5.0);
+                    let min = (lo/step).floor()*step;
+                    if self.options[key.0][key.1]["min"].is_null() {
+                        Axis::new(key.0,self,key.1 + 1).check_log_min(min);
+                        self.options[key.0][key.1]["min"] = min.into();
+                    }
+                    let axis = &mut self.options[key.0][key.1];
+                    if axis["max"].is_null() {
+                        axis["max"] = ((hi/step).ceil()*step).into();
+                    }
+                }
+            }
+        }
+        self.check_yaxes();
+        for s in &mut self.finished {
+            if let Some(decimals) = s.precision.or(self.precision) {
+                let scale = 10f64.powi(decimals as i32);
+                for p in s.data["data"].members_mut() {
+                    for v in p.members_mut() {
+                        if let Some(x) = v.as_f64() {
+                            *v = ((x*scale).round()/scale).into();
+                        }
+                    }
+                }
+            }
+        }
+        let mut ticks = Vec::new();
+        for &(key,ref log) in &self.log_axes {
+            if let Some((lo,hi)) = self.axis_range(key,true) {
+                ticks.push((key,log.ticks(lo,hi)));
+            }
+        }
+        for (key,arr) in ticks {
+            self.options[key.0][key.1]["ticks"] = arr;
+        }
+    }
+
+    // done after finish, since labels are used to match Page::color_for
+    fn label_last_values(&mut self) {
+        for s in &mut self.finished {
+            if ! s.last_value {
+                continue;
+            }
+            s.last_value = false;
+            if let Some((_,y)) = s.points().into_iter().flatten().next_back() {
+                let value = (y*100.0).round()/100.0;
+                let label = match s.data["label"].as_str() {
+                    Some(label) => format!("{} ({})",label,value),
+                    None => format!("({})",value)
+                };
+                s.data["label"] = label.into();
+            }
+        }
+    }
+
+    // series spread over several y axes, some of which are autoscaled
+    // and not positioned, often give confusing overlapping scales
+    fn check_yaxes(&self) {
+        let mut used: Vec<usize> = Vec::new();
+        for s in &self.finished {
+            let n = s.data["yaxis"].as_f64().map_or(1,|n| n as usize);
+            if ! used.contains(&n) {
+                used.push(n);
+            }
+        }
+        used.sort();
+        let unsettled: Vec<usize> = used.iter().cloned().filter(|&n| {
+            let axis = &self.options["yaxes"][n.max(1)-1];
+            axis["min"].is_null() && axis["max"].is_null() && axis["position"].is_null()
+        }).collect();
+        if used.len() > 1 && ! unsettled.is_empty() {
+            eprintln!("flot: warning: plot {:?} has series on y axes {:?} but axes {:?} have no min, max or position set",
+                self.placeholder,used,unsettled);
+        }
+    }
+
+    // stacked series must all have the same x values, since the
+    // stack plugin adds up values by position
+    fn check_stacks(&self) -> Result<(),DataError> {
+        let all_stacked = ! self.options["series"]["stack"].is_null();
+        let mut groups: Vec<(String,Vec<HashSet<u64>>)> = Vec::new();
+        for s in &self.finished {
+            let key = if ! s.data["stack"].is_null() {
+                s.data["stack"].dump()
+            } else if all_stacked {
+                "".into()
+            } else {
+                continue;
+            };
+            let xs = s.points().into_iter().filter_map(|p| p.map(|p| x_key(p.0))).collect();
+            match groups.iter().position(|g| g.0 == key) {
+                Some(i) => groups[i].1.push(xs),
+                None => groups.push((key,vec![xs]))
+            }
+        }
+        let mut bad: HashSet<u64> = HashSet::new();
+        for (_,series) in &groups {
+            for xs in series {
+                for x in xs {
+                    if series.iter().any(|other| ! other.contains(x)) {
+                        bad.insert(*x);
+                    }
+                }
+            }
+        }
+        if bad.is_empty() {
+            Ok(())
+        } else {
+            let mut bad: Vec<f64> = bad.into_iter().map(f64::from_bits).collect();
+            bad.sort_by(|a,b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            Err(DataError::Misaligned(bad))
+        }
+    }
+
+    // range of an axis: explicit min and max if set, otherwise
+    // the range of the data of the series plotted against it
+    fn axis_range(&self, key: (&str,usize), positive: bool) -> Option<(f64,f64)> {
+        let (name,coord) = if key.0 == "xaxes" {("xaxis",0)} else {("yaxis",1)};
+        let (lo,hi) = self.finished.iter()
+            .filter(|s| s.data[name].as_f64().map_or(1,|n| n as usize) == key.1 + 1)
+            .flat_map(|s| s.data["data"].members())
+            .filter_map(|p| p[coord].as_f64())
+            .filter(|&v| ! positive || v > 0.0)
+            .fold((f64::INFINITY,f64::NEG_INFINITY),|(lo,hi),v| (lo.min(v),hi.max(v)));
+        let axis = &self.options[key.0][key.1];
+        let lo = axis["min"].as_f64().unwrap_or(lo);
+        let hi = axis["max"].as_f64().unwrap_or(hi);
+        if lo <= hi {Some((lo,hi))} else {None}
+    }
+
+    /// render this plot as an HTML fragment, the placeholder div followed by
+    /// its script. Assumes jQuery and Flot are already loaded by the host page.
+    /// The plot itself is not changed, so this may be called more than once.
+    pub fn render_fragment(&mut self) -> String {
+        let p = self.prepared();
+        let mut out = Vec::new();
+        p.write_fragment(&mut out,p.base64).expect("writing to memory");
+        String::from_utf8(out).expect("plot HTML is UTF-8")
+    }
+
+    /// the Flot options set so far, as pretty-printed JSON. Options which
+    /// are Javascript functions are given by `option_functions`. Some options,
+    /// like the ticks of log axes, are only added when rendering.
+    pub fn options_json(&self) -> String {
+        self.options.pretty(4)
+    }
+
+    /// the options which are Javascript functions, as assignments
+    /// to fields of the options object like "xaxes[0].transform = ...".
+    pub fn option_functions(&self) -> &[String] {
+        &self.option_functions
+    }
+
+    /// the Flot configuration of this plot as JSON: an object with the
+    /// `options` object and the `series` array that would be passed to
+    /// `$.plot`. Options given as Javascript functions, such as tick
+    /// formatters, cannot be represented and are left out, as are settings
+    /// made on the page, such as `Page::color_for` and `Page::link_x_axes`.
+    /// The plot itself is not changed, so this may be called more than once.
+    pub fn export_config(&mut self) -> JsonValue {
+        let p = self.prepared();
+        let mut series = JsonValue::new_array();
+        for s in &p.finished {
+            if ! s.hidden {
+                series.push(s.data.clone()).unwrap();
+            }
+        }
+        object!{
+            "options" => p.options,
+            "series" => series
+        }
+    }
+
+    // a finished copy of this plot, so that it can be rendered or
+    // exported without changing the plot itself
+    fn prepared(&mut self) -> Plot {
+        let pending = mem::replace(&mut self.series, Arena::new()).into_vec();
+        let series = Arena::new();
+        for s in pending {
+            series.alloc(s.clone());
+            self.series.alloc(s);
+        }
+        let mut p = Plot {
+            series,
+            finished: self.finished.clone(),
+            placeholder: self.placeholder.clone(),
+            options: self.options.clone(),
+            time: self.time,
+            symbols: self.symbols,
+            bounds: self.bounds,
+            title: self.title.clone(),
+            option_functions: self.option_functions.clone(),
+            tick_formats: self.tick_formats.clone(),
+            log_axes: self.log_axes.clone(),
+            nice_axes: self.nice_axes.clone(),
+            description: self.description.clone(),
+            annotations: self.annotations.clone(),
+            notes: self.notes,
+            toggle_legend: self.toggle_legend,
+            compact: self.compact,
+            base64: self.base64,
+            legend_side: self.legend_side.clone(),
+            pattern: self.pattern.clone(),
+            watermark: self.watermark.clone(),
+            rotated_ticks: self.rotated_ticks.clone(),
+            precision: self.precision,
+            fluid: self.fluid,
+            click_handlers: self.click_handlers.clone(),
+            shared_tooltip: self.shared_tooltip,
+            tooltip: self.tooltip.clone(),
+            stack: self.stack,
+            errorbars: self.errorbars,
+            dashes: self.dashes,
+            axis_labels: self.axis_labels,
+            bubbles: self.bubbles,
+            percent: self.percent,
+            fillbetween: self.fillbetween,
+            linked: self.linked.clone(),
+            stats_table: self.stats_table,
+            aspect: self.aspect,
+        };
+        p.finish();
+        p.label_last_values();
+        p
+    }
+
+    // a standalone fragment must carry its own base64 decoder
+    fn write_fragment(&self, f: &mut Write, base64: bool) -> io::Result<()> {
+        self.render_placeholder(f)?;
+        write!(f,"<script type=\"text/javascript\">\n$(function () {{\n")?;
+        if base64 {
+            write!(f,"{}",BASE64_FUNCTION)?;
+        }
+        self.render_script(f)?;
+        write!(f,"}});\n</script>\n")
+    }
+
+    fn render_script(&self, f: &mut Write) -> io::Result<()> {
+        let series = &self.finished;
         let basename = "plot";
-        let mut k = 1;
-        for s in &series {
-            let varname = format!("{}_{}",basename,k);
-            k += 1;
-            write!(f,"var {} = {};\n",varname,s.data)?;
-            data += &varname;
-            data.push(',');
-        }
-        data.pop();
-        data.push(']');
+        let mut all = Vec::new();
+        let mut shown = Vec::new();
+        if self.compact {
+            write!(f,"{}",UNZIP_FUNCTION)?;
+        }
+        for (k,s) in series.iter().enumerate() {
+            let varname = format!("{}_{}",basename,k + 1);
+            let pairs = s.data["data"].members().all(|p| p.is_null() || p.len() == 2);
+            if self.base64 {
+                let mut obj = s.data.clone();
+                obj["data"] = array![];
+                writeln!(f,"var {} = {};",varname,obj)?;
+                writeln!(f,"{}.data = flot_base64(\"{}\");",varname,base64(s.data["data"].dump().as_bytes()))?;
+            } else if self.compact && pairs {
+                // x and y values as separate flat arrays
+                let mut xs = JsonValue::new_array();
+                let mut ys = JsonValue::new_array();
+                for p in s.data["data"].members() {
+                    xs.push(p[0].clone()).unwrap();
+                    ys.push(p[1].clone()).unwrap();
+                }
+                let mut obj = s.data.clone();
+                obj["data"] = array![];
+                writeln!(f,"var {} = {};",varname,obj)?;
+                writeln!(f,"{}.data = flot_unzip({},{});",varname,xs,ys)?;
+            } else {
+                writeln!(f,"var {} = {};",varname,s.data)?;
+            }
+            if ! s.hidden {
+                shown.push(varname.clone());
+            }
+            all.push(varname);
+        }
+        let hidden: Vec<_> = series.iter().map(|s| s.hidden.to_string()).collect();
         let option_var = format!("{}_options",basename);
-        write!(f,"var {} = {};\n",option_var,self.options)?;
+        writeln!(f,"var {} = {};",option_var,self.options)?;
         for lf in &self.option_functions {
-            write!(f,"{}.{};\n",option_var,lf)?;
+            writeln!(f,"{}.{};",option_var,lf)?;
+        }
+        if self.bubbles {
+            writeln!(f,"{}{}.hooks = {{drawSeries: [flot_bubbles]}};",BUBBLES_FUNCTION,option_var)?;
         }
-        write!(f,"$.plot($(\"#{}\"),{},{});\n",self.placeholder,data,option_var)
+        if self.legend_side.is_some() {
+            writeln!(f,"{}.legend = {}.legend || {{}};",option_var,option_var)?;
+            writeln!(f,"{}.legend.container = $(\"#{}_legend\");",option_var,self.placeholder)?;
+        }
+        let mut after = String::new();
+        for a in &self.annotations {
+            // divs are placed in pixels relative to the placeholder
+            let (x,y,dx,dy,style,html) = match *a {
+                Annotation::Text(x,y,ref txt) => (x,y,4,-16,"font-size:smaller".to_string(),txt.as_str()),
+                Annotation::Marker(x,y,ref color) => (x,y,-5,-5,
+                    format!("width:6px;height:6px;border-radius:50%;border:2px solid {}",color),""),
+            };
+            after += &format!("var o = {}.pointOffset({{x: {}, y: {}}});\n",basename,x,y);
+            after += &format!("$(\"#{}\").append(\"<div style='position:absolute;left:\" + (o.left + {}) + \"px;top:\" + (o.top + {}) + \"px;{}'>\" + {:?} + \"</div>\");\n",
+                self.placeholder,dx,dy,style,html);
+        }
+        if self.toggle_legend {
+            // series hidden by clicking on the legend are redrawn with empty data,
+            // so they keep their legend entry and colour
+            let legend = if self.legend_side.is_some() {
+                format!("{}_legend",self.placeholder)
+            } else {
+                self.placeholder.clone()
+            };
+            write!(f,"(function (series, options) {{
+var hidden = [{5}];
+options.legend = options.legend || {{}};
+// each label carries the index of its series, which is found again when it is clicked
+options.legend.labelFormatter = function (label, s) {{
+    var style = hidden[s.toggleIndex] ? \" style='color:#aaa'\" : \"\";
+    return \"<span data-series='\" + s.toggleIndex + \"'\" + style + \">\" + label + \"</span>\";
+}};
+function draw() {{
+    var data = [];
+    for (var i = 0; i < series.length; i++) {{
+        data.push($.extend({{}}, series[i], {{data: hidden[i] ? [] : series[i].data, toggleIndex: i}}));
+    }}
+    var {0} = $.plot($(\"#{1}\"),data,options);
+    $(\"#{6} .legendLabel\").css(\"cursor\",\"pointer\");
+{2}}}
+$(\"#{6}\").on(\"click\", \".legendLabel\", function () {{
+    var i = $(this).find(\"[data-series]\").data(\"series\");
+    if (i === undefined) return;
+    hidden[i] = ! hidden[i];
+    draw();
+}});
+draw();
+}})([{3}],{4});
+",basename,self.placeholder,after,all.join(","),option_var,hidden.join(","),legend)?;
+        } else {
+            write!(f,"var {} = $.plot($(\"#{}\"),[{}],{});\n{}",basename,self.placeholder,shown.join(","),option_var,after)?;
+        }
+        if ! self.linked.is_empty() {
+            let ids: Vec<_> = self.linked.iter().map(|id| format!("{:?}",id)).collect();
+            writeln!(f,"{}({:?},[{}]);",LINK_FUNCTION,self.placeholder,ids.join(","))?;
+        }
+        if let Some(ref format) = self.tooltip {
+            write!(f,"$(\"#{0}\").bind(\"plothover\", function (event, pos, item) {{
+    var tip = $(\"#{0}_hover\");
+    if (tip.length === 0) {{
+        tip = $(\"<div id='{0}_hover' style='position:absolute;display:none;padding:2px 4px;background:#fff;\
+border:1px solid #ccc;font-size:smaller'></div>\").appendTo(\"body\");
+    }}
+    if (item) {{
+        var format = {1};
+        tip.html(format(item.series.label, item.datapoint[0], item.datapoint[1]))
+            .css({{left: item.pageX + 8, top: item.pageY + 8}}).show();
+    }} else {{
+        tip.hide();
+    }}
+}});\n",self.placeholder,format)?;
+        }
+        if self.shared_tooltip {
+            write!(f,"$(\"#{0}\").bind(\"plothover\", function (event, pos, item) {{
+    var lines = [];
+    $.each($(\"#{0}\").data(\"plot\").getData(), function (_, s) {{
+        var best = null;
+        $.each(s.data, function (_, d) {{
+            if (d && (best === null || Math.abs(d[0] - pos.x) < Math.abs(best[0] - pos.x))) best = d;
+        }});
+        if (best !== null && s.label) {{
+            lines.push(\"<span style='color:\" + s.color + \"'>&#9632;</span> \" + s.label + \": \" + best[1]);
+        }}
+    }});
+    var tip = $(\"#{0}_tooltip\");
+    if (tip.length === 0) {{
+        tip = $(\"<div id='{0}_tooltip' style='position:absolute;display:none;padding:4px;background:#fff;\
+border:1px solid #ccc;font-size:smaller'></div>\").appendTo(\"body\");
+    }}
+    if (lines.length > 0) {{
+        tip.html(lines.join(\"<br>\")).css({{left: pos.pageX + 12, top: pos.pageY + 12}}).show();
+    }} else {{
+        tip.hide();
+    }}
+}}).bind(\"mouseleave\", function () {{
+    $(\"#{0}_tooltip\").hide();
+}});\n",self.placeholder)?;
+        }
+        for js in &self.click_handlers {
+            write!(f,"$(\"#{}\").bind(\"plotclick\", function (event, pos, item) {{
+    if (! item) return;
+    var x = item.datapoint[0], y = item.datapoint[1];
+    var label = item.series.label, index = item.dataIndex;
+    {}
+}});\n",self.placeholder,js)?;
+        }
+        Ok(())
     }
 
 }
 
 use std::env;
+use std::fs;
 use std::fs::File;
+use std::path::Path;
 use std::mem;
 use std::cell::Cell;
 
@@ -759,6 +3012,101 @@ pub struct Page {
     count: Cell<usize>,
     title: String,
     bounds: (u32,u32),
+    default_styles: bool,
+    dark: bool,
+    lang: String,
+    charset: String,
+    refresh: u32,
+    label_colors: Vec<(String,String)>,
+    base64: bool,
+    linked: Vec<Vec<usize>>,
+    offline: bool,
+    offline_dir: Option<String>,
+    assets: Option<(String,String)>,
+    columns: u32,
+}
+
+const DEFAULT_STYLES: &str = "<style type=\"text/css\">
+    html { box-sizing: border-box; }
+    *, *:before, *:after { box-sizing: inherit; }
+    body { margin: 1em 2em; font-family: Helvetica, Arial, sans-serif; line-height: 1.4; color: #333; }
+</style>";
+
+const DARK_STYLES: &str = "<style type=\"text/css\">
+    body { background: #111; color: #ddd; }
+</style>";
+
+const CDN_JQUERY: &str = "https://cdnjs.cloudflare.com/ajax/libs/jquery/3.2.1";
+const CDN_FLOT: &str = "https://cdnjs.cloudflare.com/ajax/libs/flot/0.8.3";
+
+// turns separate x and y arrays back into points; a null x is a gap
+const UNZIP_FUNCTION: &str = "function flot_unzip(xs, ys) {
+    var res = [];
+    for (var i = 0; i < xs.length; i++) {
+        res.push(xs[i] === null ? null : [xs[i], ys[i]]);
+    }
+    return res;
+}
+";
+
+// panning or zooming one plot sets the x range of the others
+const LINK_FUNCTION: &str = "(function (id, ids) {
+    $(\"#\" + id).bind(\"plotpan plotzoom\", function (event, plot) {
+        var x = plot.getXAxes()[0];
+        $.each(ids, function (_, other) {
+            var p = $(\"#\" + other).data(\"plot\");
+            if (other === id || ! p) return;
+            var opts = p.getXAxes()[0].options;
+            opts.min = x.min;
+            opts.max = x.max;
+            p.setupGrid();
+            p.draw();
+        });
+    });
+})";
+
+// draws points with their own radius (bubbles) or colour (pointColors)
+const BUBBLES_FUNCTION: &str = "function flot_bubbles(plot, ctx, s) {
+    if (! (s.bubbles || s.pointColors)) return;
+    var off = plot.getPlotOffset();
+    ctx.save();
+    ctx.translate(off.left, off.top);
+    for (var i = 0; i < s.data.length; i++) {
+        var d = s.data[i];
+        if (d === null) continue;
+        ctx.beginPath();
+        ctx.arc(s.xaxis.p2c(d[0]), s.yaxis.p2c(d[1]), s.bubbles ? d[2] : s.points.radius, 0, 2*Math.PI);
+        ctx.globalAlpha = s.bubbles ? 0.6 : 1;
+        ctx.fillStyle = s.pointColors && s.pointColors[i] || s.color;
+        ctx.fill();
+    }
+    ctx.restore();
+}
+";
+
+// series data written by Page::data_base64
+const BASE64_FUNCTION: &str = "function flot_base64(s) {
+    return JSON.parse(atob(s));
+}
+";
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// standard base64 with padding
+fn base64(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len().div_ceil(3)*4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_CHARS[(n >> (18 - 6*i)) & 63] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
 }
 
 fn script(base: &str, name: &str) -> String {
@@ -766,6 +3114,31 @@ fn script(base: &str, name: &str) -> String {
         base,name)
 }
 
+// the contents of a script file, as an inline script
+fn inline_script(dir: &str, name: &str) -> io::Result<String> {
+    let js = fs::read_to_string(Path::new(dir).join(name))?;
+    Ok(format!("<script type=\"text/javascript\">\n{}\n</script>",
+        js.replace("</script","<\\/script")))
+}
+
+// turn a local path into a file URL, percent-encoding anything
+// that isn't safe in a URL path. Windows backslashes become slashes.
+fn file_url(path: &str) -> String {
+    let mut url = "file://".to_string();
+    if ! path.starts_with('/') && ! path.starts_with('\\') {
+        url.push('/');
+    }
+    for b in path.bytes() {
+        match b {
+            b'\\' => url.push('/'),
+            b'-' | b'_' | b'.' | b'~' | b'/' | b':' => url.push(b as char),
+            _ if b.is_ascii_alphanumeric() => url.push(b as char),
+            _ => url.push_str(&format!("%{:02X}",b))
+        }
+    }
+    url
+}
+
 impl Page {
     /// create the page.
     /// If the title isn't empty then
@@ -774,8 +3147,20 @@ impl Page {
         Page {
             plots: Arena::new(),
             count: Cell::new(0),
-            title: title.into(),
+            title: escape_html(title),
             bounds: (800,300),
+            default_styles: false,
+            dark: false,
+            lang: String::new(),
+            charset: "utf-8".into(),
+            refresh: 0,
+            label_colors: Vec::new(),
+            base64: false,
+            linked: Vec::new(),
+            offline: false,
+            offline_dir: None,
+            assets: None,
+            columns: 0,
         }
     }
 
@@ -786,7 +3171,11 @@ impl Page {
         let count = &self.count;
         count.set(count.get() + 1);
         let name = format!("plot{}",self.count.get());
-        self.plots.alloc(Plot::new(&name,title,self.bounds))
+        let p = self.plots.alloc(Plot::new(&name,title,self.bounds));
+        if self.dark {
+            p.dark_theme();
+        }
+        p
     }
 
     /// the size in pixels (width,height) of _all_ the plots.
@@ -796,12 +3185,227 @@ impl Page {
         self
     }
 
+    /// set the title of the page as HTML, which is not escaped.
+    /// The document title is this text with any tags removed.
+    pub fn title_html(&mut self, html: &str) -> &mut Self {
+        self.title = html.into();
+        self
+    }
+
+    /// set the language of the document, e.g. "en" or "fr-CA".
+    pub fn lang(&mut self, code: &str) -> &mut Self {
+        self.lang = code.into();
+        self
+    }
+
+    /// set the character set of the document (default "utf-8").
+    pub fn charset(&mut self, charset: &str) -> &mut Self {
+        self.charset = charset.into();
+        self
+    }
+
+    /// give every series with this label the same colour, in all plots
+    /// on the page. Series with an explicit colour keep it.
+    pub fn color_for(&mut self, label: &str, color: &str) -> &mut Self {
+        self.label_colors.retain(|lc| lc.0 != label);
+        self.label_colors.push((label.into(),color.into()));
+        self
+    }
+
+    /// make the browser reload the page every `seconds` seconds,
+    /// for pages which are regularly rendered again. Zero means never.
+    pub fn auto_refresh(&mut self, seconds: u32) -> &mut Self {
+        self.refresh = seconds;
+        self
+    }
+
+    /// let these plots be panned and zoomed with the mouse, keeping their
+    /// x ranges the same. Plots are numbered from zero, in the order they
+    /// were created. Useful for time-aligned plots, like price above volume.
+    pub fn link_x_axes(&mut self, plots: &[usize]) -> &mut Self {
+        for &i in plots {
+            if i >= self.count.get() {
+                panic!("link_x_axes: there is no plot {}",i);
+            }
+        }
+        self.linked.push(plots.to_vec());
+        self
+    }
+
+    /// write series data as base64-encoded JSON, decoded in the browser.
+    /// This is about a third larger than plain JSON before compression,
+    /// but the page only contains simple string literals, which survive
+    /// mail clients and editors that mangle long lines of numbers.
+    pub fn data_base64(&mut self) -> &mut Self {
+        self.base64 = true;
+        self
+    }
+
+    /// load jQuery from `jquery_url` and Flot and its plugins from `flot_url`,
+    /// e.g. to use another version or a local mirror. These are the directories
+    /// containing files like "jquery.min.js" and "jquery.flot.min.js".
+    /// Overrides the `FLOT` environment variable.
+    pub fn assets_base(&mut self, jquery_url: &str, flot_url: &str) -> &mut Self {
+        self.assets = Some((jquery_url.trim_end_matches('/').into(),flot_url.trim_end_matches('/').into()));
+        self
+    }
+
+    /// put jQuery, Flot and the Flot plugins actually needed inside the
+    /// page, so that it works without network access. They are read from
+    /// the directory given by the `FLOT` environment variable.
+    pub fn offline(&mut self) -> &mut Self {
+        self.offline = true;
+        self
+    }
+
+    /// like `offline`, but reading jQuery, Flot and its plugins from `dir`,
+    /// which has the usual file names like "jquery.flot.min.js".
+    pub fn offline_from(&mut self, dir: &str) -> &mut Self {
+        self.offline = true;
+        self.offline_dir = Some(dir.into());
+        self
+    }
+
+    /// arrange the plots in a grid with `n` plots in each row.
+    /// Plots then fill the width of their column, keeping their height.
+    pub fn columns(&mut self, n: u32) -> &mut Self {
+        self.columns = n;
+        self
+    }
+
+    /// a dark page background, with `Plot::dark_theme` applied to every plot.
+    /// The theme is applied as each plot is created, so call this before `plot`;
+    /// a plot's own colour options then override the theme.
+    pub fn dark_theme(&mut self) -> &mut Self {
+        self.dark = true;
+        self
+    }
+
+    /// add a small stylesheet giving consistent margins and fonts
+    /// across browsers.
+    pub fn with_default_styles(&mut self) -> &mut Self {
+        self.default_styles = true;
+        self
+    }
 
     /// render the page as HTML to the given file.
     /// Warning: this must absolutely be the last call when
     /// creating Flot plots - any attempt to access plot
     /// objects after this will lead to tears.
+    /// Stacked series which do not share the same x values
+    /// give an `InvalidData` error.
     pub fn render(&self, file: &str) -> io::Result<()> {
+        // check the plots before creating the file, so that an
+        // invalid page does not leave an empty file behind
+        let plots = self.take_plots()?;
+        let (jquery,flot) = self.asset_bases();
+        let mut f = File::create(file)?;
+        self.write_page(&mut f,&plots,&jquery,&flot)
+    }
+
+    /// render the page as HTML to any writer, such as a `Vec<u8>`,
+    /// socket or compressing encoder. Like `render`, this must be the last call.
+    pub fn render_to_writer<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let plots = self.take_plots()?;
+        let (jquery,flot) = self.asset_bases();
+        self.write_page(w,&plots,&jquery,&flot)
+    }
+
+    /// the page as a string of HTML. Like `render`, this must be the last call.
+    /// Panics if the page cannot be rendered, e.g. if stacked series do not
+    /// share the same x values.
+    pub fn to_html(&self) -> String {
+        let mut out = Vec::new();
+        self.render_to_writer(&mut out).expect("cannot render page");
+        String::from_utf8(out).expect("page HTML is UTF-8")
+    }
+
+    /// render each plot as a separate HTML fragment (a div and a script),
+    /// so that an application can place them in its own page. The page
+    /// title is not used. Returns the fragments, and the `<script>` tags
+    /// for jQuery, Flot and the Flot plugins which the page must also load.
+    /// Like `render`, this must be the last call made on the plots.
+    pub fn render_fragments(&self) -> io::Result<(Vec<String>,Vec<String>)> {
+        let plots = self.take_plots()?;
+        let mut res = Vec::new();
+        for p in &plots {
+            let mut out = Vec::new();
+            p.write_fragment(&mut out,self.base64)?;
+            res.push(String::from_utf8(out).expect("plot HTML is UTF-8"));
+        }
+        Ok((res,self.dependency_scripts(&plots)))
+    }
+
+    // the <script> tags for jQuery, Flot and the plugins these plots need
+    fn dependency_scripts(&self, plots: &[Plot]) -> Vec<String> {
+        let (jquery,flot) = self.asset_bases();
+        let mut res = vec![script(&jquery,"jquery.min.js"),script(&flot,"jquery.flot.min.js")];
+        for plugin in plugins(plots) {
+            res.push(script(&flot,plugin));
+        }
+        res
+    }
+
+    /// render just the plots, without the page title or the rest of the
+    /// HTML document, to paste into an existing page. Returns the `<script>`
+    /// tags for jQuery, Flot and the Flot plugins which that page must load.
+    /// Like `render`, this must be the last call.
+    pub fn render_fragment<W: Write>(&self, w: &mut W) -> io::Result<Vec<String>> {
+        let plots = self.take_plots()?;
+        if self.columns > 0 {
+            self.write_grid_styles(w)?;
+        }
+        self.write_plots(w,&plots)?;
+        Ok(self.dependency_scripts(&plots))
+    }
+
+    /// render the page as gzip-compressed HTML to `path.html.gz`.
+    /// Needs the `flate2` feature.
+    #[cfg(feature = "flate2")]
+    pub fn render_gzip(&self, path: &str) -> io::Result<()> {
+        let f = File::create(format!("{}.html.gz",path))?;
+        let mut gz = flate2::write::GzEncoder::new(f,flate2::Compression::default());
+        self.render_to_writer(&mut gz)?;
+        gz.finish()?;
+        Ok(())
+    }
+
+    /// render the page as `name.html` inside the directory `dir`, which is
+    /// created if needed. If the `FLOT` environment variable points to local
+    /// copies of jQuery and Flot, the files needed are copied into `dir` and
+    /// referenced with relative paths, so the directory can be moved as a whole.
+    /// Like `render`, this must be the last call.
+    pub fn render_into_dir(&self, dir: &str, name: &str) -> io::Result<()> {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir)?;
+        let plots = self.take_plots()?;
+        let (jquery,flot) = if let Some(ref assets) = self.assets {
+            assets.clone()
+        } else if let Ok(src) = env::var("FLOT") {
+            let src = Path::new(&src);
+            let mut libs = vec!["jquery.min.js","jquery.flot.min.js"];
+            libs.extend(plugins(&plots));
+            for lib in libs {
+                fs::copy(src.join(lib),dir.join(lib))?;
+            }
+            (".".to_string(),".".to_string())
+        } else {
+            (CDN_JQUERY.to_string(),CDN_FLOT.to_string())
+        };
+        let mut f = File::create(dir.join(format!("{}.html",name)))?;
+        self.write_page(&mut f,&plots,&jquery,&flot)
+    }
+
+    fn asset_bases(&self) -> (String,String) {
+        match self.assets {
+            Some(ref assets) => assets.clone(),
+            None => asset_bases()
+        }
+    }
+
+    // stacked plots are checked for consistent x values, which
+    // is reported as invalid data
+    fn take_plots(&self) -> io::Result<Vec<Plot>> {
         // this is deeply dubious. In an ideal world with non-lexical lifetimes,
         // this could be a self method, since it is _only_ called after all
         // the plots have been defined. It cannot be &mut self, because borrows
@@ -814,45 +3418,259 @@ impl Page {
         let mut_self: &mut Self = unsafe { &mut * (self_ptr as *mut Self) };
         mem::swap(&mut mut_self.plots, &mut nplots);
 
-        let plots = nplots.into_vec();
+        let mut plots = nplots.into_vec();
+        for group in &self.linked {
+            let ids: Vec<_> = group.iter().map(|&i| plots[i].placeholder.clone()).collect();
+            for &i in group {
+                let p = &mut plots[i];
+                p.linked.extend(ids.iter().cloned());
+                p.options["zoom"]["interactive"] = true.into();
+                p.options["pan"]["interactive"] = true.into();
+            }
+        }
+        for p in &mut plots {
+            p.fluid = self.columns > 0;
+            p.finish();
+            p.base64 = self.base64;
+            for s in &mut p.finished {
+                if ! s.data["color"].is_null() {
+                    continue;
+                }
+                let color = self.label_colors.iter()
+                    .find(|lc| s.data["label"] == lc.0.as_str()).map(|lc| lc.1.as_str());
+                if let Some(color) = color {
+                    s.data["color"] = color.into();
+                }
+            }
+            p.label_last_values();
+            if let Err(e) = p.check_stacks() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,e));
+            }
+        }
+        let mut ids: Vec<&str> = Vec::new();
+        for p in &plots {
+            let series_ids = p.finished.iter().filter_map(|s| s.data["id"].as_str());
+            for id in Some(p.placeholder.as_str()).into_iter().chain(series_ids) {
+                if ids.contains(&id) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                        format!("the id {:?} is used more than once",id)));
+                }
+                ids.push(id);
+            }
+        }
+        Ok(plots)
+    }
 
-        let (jquery,flot) = if let Ok(f) = env::var("FLOT") {
-            let local = format!("file://{}",f);
-            (local.clone(),local.clone())
+    fn write_page(&self, f: &mut Write, plots: &[Plot], jquery: &str, flot: &str) -> io::Result<()> {
+        let lang = if ! self.lang.is_empty() {
+            format!(" lang={:?}",self.lang)
         } else {
-            (
-                "https://cdnjs.cloudflare.com/ajax/libs/jquery/3.2.1".to_string(),
-                "https://cdnjs.cloudflare.com/ajax/libs/flot/0.8.3".to_string()
-            )
+            String::new()
         };
-        let mut f = File::create(file)?;
         let header = format!("
-<html>
+<html{}>
  <head>
-    <meta http-equiv=\"Content-Type\" content=\"text/html; charset=utf-8\">
+    <meta http-equiv=\"Content-Type\" content=\"text/html; charset={}\">
     <title>{}</title>
-", if ! self.title.is_empty() {&self.title} else {"Flot"});
+", lang, self.charset, if ! self.title.is_empty() {strip_tags(&self.title)} else {"Flot".into()});
+        let dir = match self.offline_dir {
+            Some(ref dir) => Some(dir.clone()),
+            None if self.offline => Some(flot_env_dir()?),
+            None => None
+        };
+        let tag = |base: &str, name: &str| match dir {
+            Some(ref dir) => inline_script(dir,name),
+            None => Ok(script(base,name))
+        };
         write!(f,"{}{}\n{}\n",header,
-            script(&jquery,"jquery.min.js"),
-            script(&flot,"jquery.flot.min.js"))?;
-        if plots.iter().any(|p| p.time) {
-            write!(f,"{}\n",script(&flot,"jquery.flot.time.min.js"))?;
+            tag(jquery,"jquery.min.js")?,
+            tag(flot,"jquery.flot.min.js")?)?;
+        for plugin in plugins(plots) {
+            writeln!(f,"{}",tag(flot,plugin)?)?;
+        }
+        if self.refresh > 0 {
+            writeln!(f,"<meta http-equiv=\"refresh\" content=\"{}\">",self.refresh)?;
+        }
+        if self.default_styles {
+            writeln!(f,"{}",DEFAULT_STYLES)?;
         }
-        if plots.iter().any(|p| p.symbols) {
-            write!(f,"{}\n",script(&flot,"jquery.flot.symbol.min.js"))?;
+        if self.dark {
+            writeln!(f,"{}",DARK_STYLES)?;
         }
-        write!(f,"</head>\n</body>\n")?;
+        if self.columns > 0 {
+            self.write_grid_styles(f)?;
+        }
+        write!(f,"</head>\n<body>\n")?;
         if ! self.title.is_empty() {
-            write!(f,"<h1>{}</h1>\n",self.title)?;
+            writeln!(f,"<h1>{}</h1>",self.title)?;
         }
-        for p in &plots {
-            p.render_placeholder(&mut f)?;
+        self.write_plots(f,plots)?;
+        write!(f,"</body>\n</html>\n")
+    }
+
+    fn write_grid_styles(&self, f: &mut Write) -> io::Result<()> {
+        write!(f,"<style type=\"text/css\">
+    .flot-grid {{ display: grid; grid-template-columns: repeat({}, 1fr); gap: 1em; }}
+    .flot-grid > div {{ min-width: 0; }}
+</style>\n",self.columns)
+    }
+
+    // the placeholders of the plots followed by the script drawing them
+    fn write_plots(&self, f: &mut Write, plots: &[Plot]) -> io::Result<()> {
+        if self.columns > 0 {
+            writeln!(f,"<div class=\"flot-grid\">")?;
+        }
+        for p in plots {
+            if self.columns > 0 {
+                writeln!(f,"<div>")?;
+                p.render_placeholder(f)?;
+                writeln!(f,"</div>")?;
+            } else {
+                p.render_placeholder(f)?;
+            }
+        }
+        if self.columns > 0 {
+            writeln!(f,"</div>")?;
         }
         write!(f,"<script type=\"text/javascript\">\n$(function () {{\n")?;
+        if self.base64 {
+            write!(f,"{}",BASE64_FUNCTION)?;
+        }
         for p in plots {
-            p.render_script(&mut f)?;
+            p.render_script(f)?;
         }
-        write!(f,"}});\n</script>\n</body>\n</html>\n")
+        write!(f,"}});\n</script>\n")
+    }
+}
+
+// where jQuery and Flot are loaded from: the FLOT environment
+// variable can point to local copies, otherwise a CDN is used
+fn asset_bases() -> (String,String) {
+    if let Ok(f) = env::var("FLOT") {
+        let local = file_url(&f);
+        (local.clone(),local)
+    } else {
+        (CDN_JQUERY.to_string(),CDN_FLOT.to_string())
+    }
+}
+
+// where Page::offline reads jQuery and Flot from
+fn flot_env_dir() -> io::Result<String> {
+    env::var("FLOT").map_err(|_| io::Error::new(io::ErrorKind::NotFound,
+        "offline pages need the FLOT environment variable to point at jQuery and Flot"))
+}
+
+// the Flot plugins needed by these plots
+fn plugins(plots: &[Plot]) -> Vec<&'static str> {
+    let mut res = Vec::new();
+    if plots.iter().any(|p| p.time) {
+        res.push("jquery.flot.time.min.js");
     }
+    if plots.iter().any(|p| p.symbols) {
+        res.push("jquery.flot.symbol.min.js");
+    }
+    if plots.iter().any(|p| p.stack) {
+        res.push("jquery.flot.stack.min.js");
+    }
+    if plots.iter().any(|p| p.errorbars) {
+        res.push("jquery.flot.errorbars.min.js");
+    }
+    if plots.iter().any(|p| p.dashes) {
+        res.push("jquery.flot.dashes.js");
+    }
+    if plots.iter().any(|p| p.axis_labels) {
+        res.push("jquery.flot.axislabels.js");
+    }
+    if plots.iter().any(|p| p.fillbetween) {
+        res.push("jquery.flot.fillbetween.min.js");
+    }
+    if plots.iter().any(|p| ! p.linked.is_empty()) {
+        res.push("jquery.flot.navigate.min.js");
+    }
+    res
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_urls() {
+        assert_eq!(file_url("C:\\my dir\\flot"), "file:///C:/my%20dir/flot");
+        assert_eq!(file_url("/home/me/my flot"), "file:///home/me/my%20flot");
+    }
+    #[test]
+    fn one_body() {
+        let page = Page::new("body");
+        page.plot("plot").lines("line",vec![(0.0,1.0),(1.0,2.0)]);
+        let html = page.to_html();
+        assert_eq!(html.matches("<body>").count(), 1);
+        assert_eq!(html.matches("</body>").count(), 1);
+    }
+    #[test]
+    fn axis_grid_lines() {
+        let page = Page::new("");
+        let p = page.plot("");
+        p.xaxis().grid_lines(false);
+        p.yaxis().grid_lines(true);
+        assert_eq!(p.options["xaxes"][0]["tickLength"], 0);
+        assert!(p.options["yaxes"][0]["tickLength"].is_null());
+    }
+    #[test]
+    fn symbol_plugin() {
+        let page = Page::new("");
+        page.plot("").points("cross",vec![(0.0,1.0)]).symbol("cross").radius(2);
+        assert!(page.to_html().contains("jquery.flot.symbol.min.js"));
+        let page = Page::new("");
+        page.plot("").points("circle",vec![(0.0,1.0)]).symbol("circle").radius(2);
+        assert!(! page.to_html().contains("jquery.flot.symbol.min.js"));
+    }
+    #[test]
+    fn export_config_repeatable() {
+        let page = Page::new("");
+        let p = page.plot("");
+        p.lines("line",vec![(0.0,1.0),(1.0,2.0)]).show_last_value();
+        let first = p.export_config();
+        assert_eq!(first, p.export_config());
+        assert_eq!(first["series"][0]["label"], "line (2)");
+        assert_eq!(p.finished.len(), 0);
+    }
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b""), "");
+    }
+
+    #[test]
+    fn lttb_keeps_ends() {
+        let data: Vec<_> = (0..100).map(|i| (i as f64, (i as f64).sin())).collect();
+        let res = lttb(&data,10);
+        assert_eq!(res.len(), 10);
+        assert_eq!(res[0], data[0]);
+        assert_eq!(res[9], data[99]);
+        assert_eq!(lttb(&data,200).len(), 100);
+    }
+
+    #[test]
+    fn monotone_cubic_through_points() {
+        let pts = vec![(0.0,0.0),(1.0,1.0),(2.0,1.0),(3.0,5.0)];
+        let res = monotone_cubic(&pts,4);
+        assert_eq!(res.len(), 13);
+        for (k,p) in pts.iter().enumerate() {
+            assert_eq!(res[4*k], *p);
+        }
+        // no overshoot on the flat interval
+        assert!(res[4..9].iter().all(|p| p.1 == 1.0));
+    }
+
+    #[test]
+    fn interpolate_range_and_nan() {
+        let data = vec![(0.0,0.0),(2.0,4.0),(f64::NAN,1.0)];
+        let res = interpolate(&data,&[-1.0,0.0,1.0,2.0,3.0,f64::NAN]);
+        assert_eq!(res, vec![(0.0,0.0),(1.0,2.0),(2.0,4.0)]);
+        assert!(interpolate(&[],&[1.0]).is_empty());
+    }
+}